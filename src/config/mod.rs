@@ -1,18 +1,47 @@
 use mongodb::{bson::doc, options::IndexOptions, Collection, Database, IndexModel};
-use std::env;
+use std::{env, sync::Arc, time::Duration};
 
-use crate::models::{file::FileCollection, token::TokenCollection, user::UserCollection};
+use crate::{
+    kms::{load_key_provider, KeyProvider, KeyProviderKind},
+    models::{
+        cron_job::CronJobCollection, download::DownloadCollection,
+        download_ticket::DownloadTicketCollection, file::FileCollection,
+        share_link::ShareLinkCollection, token::TokenCollection, upload::UploadSessionCollection,
+        user::UserCollection,
+    },
+    storage::{load_storage_backend, StorageBackend, StorageKind},
+    utils::{hashing::hash_token, signed_url::SignedUrlKeys},
+};
 
 pub struct AppConfig {
     pub server_url: String,
+    // Base URL clients reach the server at, for links returned in responses
+    pub public_url: String,
     pub mongodb_url: String,
     pub sendgrid_api_key: String,
     pub sendgrid_sender_name: String,
     pub sendgrid_sender_email: String,
     pub jwt_secret_key: String,
-    pub cloudinary_cloud_name: String,
-    pub cloudinary_api_key: String,
-    pub cloudinary_api_secret: String,
+    pub storage_backend: StorageKind,
+    pub local_storage_path: String,
+    pub cloudinary_cloud_name: Option<String>,
+    pub cloudinary_api_key: Option<String>,
+    pub cloudinary_api_secret: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: String,
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: String,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    pub key_provider: KeyProviderKind,
+    pub master_key_file: String,
+    pub key_store_path: String,
+    // Cron expressions (with seconds) of the background jobs
+    pub file_sweep_schedule: String,
+    pub token_cleanup_schedule: String,
+    pub upload_session_cleanup_schedule: String,
+    // Grants access to `/admin`; the admin API is disabled without it
+    pub admin_api_key: Option<String>,
 }
 
 #[derive(Clone)]
@@ -20,14 +49,32 @@ pub struct AppState {
     pub user_collection: Collection<UserCollection>,
     pub token_collection: Collection<TokenCollection>,
     pub file_collection: Collection<FileCollection>,
+    pub download_collection: Collection<DownloadCollection>,
+    pub download_ticket_collection: Collection<DownloadTicketCollection>,
+    pub share_link_collection: Collection<ShareLinkCollection>,
+    pub upload_session_collection: Collection<UploadSessionCollection>,
+    pub cron_job_collection: Collection<CronJobCollection>,
+    pub storage: Arc<dyn StorageBackend>,
+    pub key_provider: Arc<dyn KeyProvider>,
+    // Read once at startup, see `AppConfig`
+    pub public_url: String,
+    pub signed_url_keys: SignedUrlKeys,
+    // Hash of `ADMIN_API_KEY`, see `ExtractAdmin`
+    pub admin_key_hash: Option<String>,
 }
 
 impl AppConfig {
     pub fn load_config() -> Self {
         dotenvy::dotenv().expect("Unable to access .env file!");
 
-        let app_config = AppConfig {
-            server_url: env::var("SERVER_URL").unwrap_or("127.0.0.1:8000".to_string()),
+        let server_url = env::var("SERVER_URL").unwrap_or("127.0.0.1:8000".to_string());
+
+        AppConfig {
+            public_url: env::var("PUBLIC_URL")
+                .unwrap_or(format!("http://{}", server_url))
+                .trim_end_matches('/')
+                .to_string(),
+            server_url,
             mongodb_url: env::var("MONGODB_URL").expect("MONGODB_URL not found in .env"),
             sendgrid_api_key: env::var("SENDGRID_API_KEY")
                 .expect("SENDGRID_API_KEY not found in .env"),
@@ -36,15 +83,34 @@ impl AppConfig {
             sendgrid_sender_email: env::var("SENDGRID_SENDER_EMAIL")
                 .expect("SENDGRID_SENDER_EMAIL not found in .env"),
             jwt_secret_key: env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY not found in .env"),
-            cloudinary_cloud_name: env::var("CLOUDINARY_CLOUD_NAME")
-                .expect("CLOUDINARY_CLOUD_NAME not found in .env"),
-            cloudinary_api_key: env::var("CLOUDINARY_API_KEY")
-                .expect("CLOUDINARY_API_KEY not found in .env"),
-            cloudinary_api_secret: env::var("CLOUDINARY_API_SECRET")
-                .expect("CLOUDINARY_API_SECRET not found in .env"),
-        };
-
-        app_config
+            storage_backend: env::var("STORAGE_BACKEND")
+                .unwrap_or("cloudinary".to_string())
+                .parse()
+                .expect("STORAGE_BACKEND should be one of `cloudinary`, `local` or `s3`"),
+            local_storage_path: env::var("LOCAL_STORAGE_PATH").unwrap_or("./uploads".to_string()),
+            cloudinary_cloud_name: env::var("CLOUDINARY_CLOUD_NAME").ok(),
+            cloudinary_api_key: env::var("CLOUDINARY_API_KEY").ok(),
+            cloudinary_api_secret: env::var("CLOUDINARY_API_SECRET").ok(),
+            s3_endpoint: env::var("S3_ENDPOINT").ok(),
+            s3_region: env::var("S3_REGION").unwrap_or("us-east-1".to_string()),
+            s3_bucket: env::var("S3_BUCKET").ok(),
+            s3_prefix: env::var("S3_PREFIX").unwrap_or_default(),
+            s3_access_key_id: env::var("S3_ACCESS_KEY_ID").ok(),
+            s3_secret_access_key: env::var("S3_SECRET_ACCESS_KEY").ok(),
+            key_provider: env::var("KEY_PROVIDER")
+                .unwrap_or("local".to_string())
+                .parse()
+                .expect("KEY_PROVIDER should be `local`"),
+            master_key_file: env::var("MASTER_KEY_FILE").unwrap_or("./master.key".to_string()),
+            key_store_path: env::var("KEY_STORE_PATH").unwrap_or("./keys".to_string()),
+            file_sweep_schedule: env::var("FILE_SWEEP_SCHEDULE")
+                .unwrap_or("0 */1 * * * *".to_string()),
+            token_cleanup_schedule: env::var("TOKEN_CLEANUP_SCHEDULE")
+                .unwrap_or("0 0 * * * *".to_string()),
+            upload_session_cleanup_schedule: env::var("UPLOAD_SESSION_CLEANUP_SCHEDULE")
+                .unwrap_or("0 */10 * * * *".to_string()),
+            admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
+        }
     }
 }
 
@@ -52,6 +118,9 @@ impl AppState {
     pub async fn load_state() -> Self {
         let app_config = AppConfig::load_config();
 
+        let storage = load_storage_backend(&app_config);
+        let key_provider = load_key_provider(&app_config);
+
         let db = mongodb::Client::with_uri_str(app_config.mongodb_url)
             .await
             .unwrap()
@@ -60,15 +129,35 @@ impl AppState {
         // Set up indexes BEFORE using collections
         Self::get_user_collection(&db).await.unwrap();
         Self::get_token_collection(&db).await.unwrap();
+        Self::get_file_collection(&db).await.unwrap();
+        Self::get_download_collection(&db).await.unwrap();
+        Self::get_download_ticket_collection(&db).await.unwrap();
+        Self::get_share_link_collection(&db).await.unwrap();
 
         let user_collection = db.collection::<UserCollection>("users");
         let token_collection = db.collection::<TokenCollection>("tokens");
         let file_collection = db.collection::<FileCollection>("files");
+        let download_collection = db.collection::<DownloadCollection>("downloads");
+        let download_ticket_collection =
+            db.collection::<DownloadTicketCollection>("download_tickets");
+        let share_link_collection = db.collection::<ShareLinkCollection>("share_links");
+        let upload_session_collection = db.collection::<UploadSessionCollection>("upload_sessions");
+        let cron_job_collection = db.collection::<CronJobCollection>("cron_jobs");
 
         AppState {
             user_collection,
             token_collection,
             file_collection,
+            download_collection,
+            download_ticket_collection,
+            share_link_collection,
+            upload_session_collection,
+            cron_job_collection,
+            storage,
+            key_provider,
+            public_url: app_config.public_url,
+            signed_url_keys: SignedUrlKeys::new(&app_config.jwt_secret_key),
+            admin_key_hash: app_config.admin_api_key.as_deref().map(hash_token),
         }
     }
 
@@ -91,6 +180,84 @@ impl AppState {
         Ok(())
     }
 
+    async fn get_file_collection(db: &Database) -> mongodb::error::Result<()> {
+        let file_collection = db.collection::<FileCollection>("files");
+
+        // Files are shared by their `share_code`; older files don't have one
+        let index_model = IndexModel::builder()
+            .keys(doc! { "share_code": 1 })
+            .options(IndexOptions::builder().unique(true).sparse(true).build())
+            .build();
+
+        file_collection.create_index(index_model).await?;
+
+        Ok(())
+    }
+
+    async fn get_download_collection(db: &Database) -> mongodb::error::Result<()> {
+        let download_collection = db.collection::<DownloadCollection>("downloads");
+
+        // Download history of a file, newest first
+        let file_index = IndexModel::builder()
+            .keys(doc! { "file_id": 1, "downloaded_at": -1 })
+            .build();
+        // Downloads of all files of a user, for stats
+        let user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "downloaded_at": -1 })
+            .build();
+
+        download_collection
+            .create_indexes([file_index, user_index])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_download_ticket_collection(db: &Database) -> mongodb::error::Result<()> {
+        let download_ticket_collection =
+            db.collection::<DownloadTicketCollection>("download_tickets");
+
+        // Tickets are redeemed by the hash of the ticket
+        let ticket_index = IndexModel::builder()
+            .keys(doc! { "ticket_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        // Tickets nobody redeemed are removed by MongoDB once they expire
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+
+        download_ticket_collection
+            .create_indexes([ticket_index, expiry_index])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_share_link_collection(db: &Database) -> mongodb::error::Result<()> {
+        let share_link_collection = db.collection::<ShareLinkCollection>("share_links");
+
+        // Links are looked up by the hash of their token
+        let token_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        let file_index = IndexModel::builder()
+            .keys(doc! { "file_id": 1, "created_at": -1 })
+            .build();
+
+        share_link_collection
+            .create_indexes([token_index, file_index])
+            .await?;
+
+        Ok(())
+    }
+
     async fn get_user_collection(db: &Database) -> mongodb::error::Result<()> {
         let user_collection = db.collection::<UserCollection>("users");
 
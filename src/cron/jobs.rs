@@ -0,0 +1,166 @@
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc};
+
+use super::JobItems;
+use crate::{
+    config::AppState,
+    error::AppError,
+    handler::{
+        lifecycle::{purge_file, set_file_status, set_files_status, stored_status_filter},
+        upload::{delete_parts, destroy_session_keys},
+    },
+    models::{
+        download::DOWNLOAD_SESSION_MINUTES,
+        file::{FileCollection, FileStatus},
+    },
+    utils::misc::date_to_bson,
+};
+
+/// How long a purge may take before a later sweep retries it.
+const PURGE_RETRY_MINUTES: i64 = 10;
+
+/// Moves files through their lifecycle: records expiries and used-up downloads, then
+/// purges every file that can no longer be downloaded.
+pub async fn sweep_files(app_state: &AppState) -> Result<JobItems, AppError> {
+    let now = Utc::now();
+
+    // Expiries and downloads no download recorded yet
+    let expired = set_files_status(
+        app_state,
+        doc! {"purge": null, "expires_at": {"$lte": date_to_bson(&now)?}},
+        FileStatus::Expired,
+    )
+    .await?;
+    let exhausted = set_files_status(
+        app_state,
+        doc! {
+            "purge": null,
+            "expires_at": {"$gt": date_to_bson(&now)?},
+            "$expr": {"$gte": ["$download_count", "$max_downloads"]},
+        },
+        FileStatus::Exhausted,
+    )
+    .await?;
+
+    let mut files = app_state
+        .file_collection
+        .find(doc! {
+            "$or": [
+                {"status": stored_status_filter(&[FileStatus::Expired, FileStatus::Revoked])?},
+                // Until then the last download can still be resumed
+                {
+                    "status": stored_status_filter(&[FileStatus::Exhausted])?,
+                    "status_changed_at": {
+                        "$lte": date_to_bson(&(now - Duration::minutes(DOWNLOAD_SESSION_MINUTES)))?,
+                    },
+                },
+                // Left behind by a sweep that failed half-way
+                {
+                    "status": stored_status_filter(&[FileStatus::Purging])?,
+                    "status_changed_at": {
+                        "$lte": date_to_bson(&(now - Duration::minutes(PURGE_RETRY_MINUTES)))?,
+                    },
+                },
+            ],
+        })
+        .await?;
+
+    let mut purged = 0;
+    let mut failed = 0;
+    while let Some(file) = files
+        .try_next()
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in fetching files to purge{}", e)))?
+    {
+        // A file whose own downloads are used up can still be shared by its links
+        if file.status == FileStatus::Exhausted && has_active_share_links(app_state, &file).await? {
+            continue;
+        }
+
+        // Unless a download or another sweep got to it first
+        let Some(file) = set_file_status(
+            app_state,
+            doc! {"_id": file.id, "status": stored_status_filter(&[file.status])?},
+            FileStatus::Purging,
+            doc! {},
+        )
+        .await?
+        else {
+            continue;
+        };
+
+        // A later sweep retries it
+        match purge_file(app_state, &file).await {
+            Ok(()) => purged += 1,
+            Err(e) => {
+                tracing::error!("Error in purging file {:?}: {:?}", file.id, e);
+                failed += 1;
+            }
+        }
+    }
+
+    Ok(JobItems::from([
+        ("expired".to_string(), expired),
+        ("exhausted".to_string(), exhausted),
+        ("purged".to_string(), purged),
+        ("purge_failed".to_string(), failed),
+    ]))
+}
+
+/// Whether a file has share links that can still be used to download it.
+async fn has_active_share_links(
+    app_state: &AppState,
+    file: &FileCollection,
+) -> Result<bool, AppError> {
+    let active_links = app_state
+        .share_link_collection
+        .count_documents(doc! {
+            "file_id": file.id,
+            "revoked_at": null,
+            "expires_at": {"$gt": date_to_bson(&Utc::now())?},
+            "$expr": {"$lt": ["$download_count", "$max_downloads"]},
+        })
+        .await?;
+
+    Ok(active_links > 0)
+}
+
+/// Removes email verification and password reset tokens once they expired.
+pub async fn delete_expired_tokens(app_state: &AppState) -> Result<JobItems, AppError> {
+    let delete_result = app_state
+        .token_collection
+        .delete_many(doc! {"expires_at": {"$lt": date_to_bson(&Utc::now())?}})
+        .await?;
+
+    Ok(JobItems::from([(
+        "deleted".to_string(),
+        delete_result.deleted_count,
+    )]))
+}
+
+/// Removes tus uploads that were not completed in time, along with their parts.
+pub async fn delete_expired_upload_sessions(app_state: &AppState) -> Result<JobItems, AppError> {
+    let doc = doc! {"session_expires_at": { "$lt": bson::DateTime::now() }};
+
+    let mut sessions = app_state.upload_session_collection.find(doc).await?;
+
+    let mut deleted = 0;
+    while let Some(session) = sessions
+        .try_next()
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in fetching upload sessions{}", e)))?
+    {
+        delete_parts(app_state, &session.parts).await?;
+        destroy_session_keys(app_state, &session, false).await?;
+
+        app_state
+            .upload_session_collection
+            .delete_one(doc! {"_id": session.id})
+            .await?;
+
+        deleted += 1;
+    }
+
+    Ok(JobItems::from([("deleted".to_string(), deleted)]))
+}
@@ -1,28 +1,73 @@
-use chrono::Utc;
-use futures::TryStreamExt;
-use mongodb::bson::doc;
+use std::{collections::BTreeMap, time::Instant};
+
+use chrono::{Duration, Utc};
+use mongodb::bson::{self, doc};
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 
-use crate::{config::AppState, utils::cloudinary};
+use crate::{
+    config::{AppConfig, AppState},
+    error::AppError,
+    models::cron_job::{CronJob, JobOutcome, JobRun, JobTrigger},
+    utils::misc::date_to_bson,
+};
+
+pub mod jobs;
+
+/// What a run of a job got done, by kind of item.
+pub type JobItems = BTreeMap<String, u64>;
+
+/// How long a run may take before it is presumed dead (e.g. the server was stopped
+/// half-way) and the job may run again.
+const STALE_RUN_MINUTES: i64 = 60;
+
+/// The schedule of a job, from the config.
+pub fn job_schedule(job: CronJob, app_config: &AppConfig) -> &str {
+    match job {
+        CronJob::FileSweep => &app_config.file_sweep_schedule,
+        CronJob::TokenCleanup => &app_config.token_cleanup_schedule,
+        CronJob::UploadSessionCleanup => &app_config.upload_session_cleanup_schedule,
+    }
+}
 
-pub async fn auto_delete_file_from_server(app_state: AppState) -> Result<(), JobSchedulerError> {
+/// Registers every `CronJob` in the database and runs each on its schedule.
+///
+/// Jobs paused through the admin API keep skipping their scheduled runs after a restart.
+///
+/// # Errors
+/// * `JobSchedulerError` if a schedule is not a valid cron expression (with seconds),
+///   or the jobs can not be registered.
+pub async fn start_cron_jobs(app_state: AppState) -> Result<(), JobSchedulerError> {
+    let app_config = AppConfig::load_config();
     let sched = JobScheduler::new().await?;
 
-    // Add basic cron job
-    sched
-        .add(Job::new(
-            "0 */1 * * * *", // Run every 1 min
-            move |_uuid, _l| {
-                tracing::info!("Running cron job at {}", Utc::now());
+    for job in CronJob::ALL {
+        let schedule = job_schedule(job, &app_config).to_string();
+
+        app_state
+            .cron_job_collection
+            .update_one(
+                doc! {"_id": job.to_string()},
+                doc! {
+                    "$set": {"schedule": &schedule},
+                    "$setOnInsert": {"is_paused": false},
+                },
+            )
+            .upsert(true)
+            .await
+            .map_err(|_| JobSchedulerError::CantAdd)?;
+
+        let app_state = app_state.clone();
+        sched
+            .add(Job::new(schedule, move |_uuid, _l| {
                 let app_state = app_state.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = delete_file_from_cloud(app_state).await {
-                        tracing::error!("Error in delete_file_from_cloud: {:?}", e);
+                    if let Err(e) = run_job(app_state, job, JobTrigger::Schedule).await {
+                        tracing::error!("Error in running {}: {:?}", job, e);
                     }
                 });
-            },
-        )?)
-        .await?;
+            })?)
+            .await?;
+    }
 
     // Start the scheduler
     sched.start().await?;
@@ -30,30 +75,86 @@ pub async fn auto_delete_file_from_server(app_state: AppState) -> Result<(), Job
     Ok(())
 }
 
-async fn delete_file_from_cloud(app_state: AppState) -> Result<(), JobSchedulerError> {
-    let now = Utc::now();
-    let doc = doc! {
+/// Runs a job and records the run, its duration, outcome and item counts on the job.
+///
+/// Scheduled runs of paused jobs are skipped, and so is any run while the job is
+/// already running (on this or another instance).
+///
+/// # Returns
+/// * `Ok(Some(JobRun))` once the job ran, whether it succeeded or not.
+/// * `Ok(None)` if it was skipped.
+pub async fn run_job(
+    app_state: AppState,
+    job: CronJob,
+    trigger: JobTrigger,
+) -> Result<Option<JobRun>, AppError> {
+    let started_at = Utc::now();
+    let stale_since = started_at - Duration::minutes(STALE_RUN_MINUTES);
+
+    let mut filter = doc! {
+        "_id": job.to_string(),
         "$or": [
-            {"expires_at": { "$lt": now.to_string() }},
-            {"$expr": { "$gte": ["$download_count", "$max_downloads"] }}
-        ]
+            {"running_since": null},
+            {"running_since": {"$lt": date_to_bson(&stale_since)?}},
+        ],
     };
+    if trigger == JobTrigger::Schedule {
+        filter.insert("is_paused", false);
+    }
 
-    let mut files = app_state
-        .file_collection
-        .find(doc)
-        .await
-        .map_err(|_| JobSchedulerError::FetchJob)?;
-
-    while let Some(file) = files
-        .try_next()
-        .await
-        .map_err(|_| JobSchedulerError::GetJobData)?
-    {
-        cloudinary::delete_file_from_cloud(file.cid)
-            .await
-            .map_err(|_| JobSchedulerError::CantRemove)?;
+    let is_claimed = app_state
+        .cron_job_collection
+        .update_one(
+            filter,
+            doc! {"$set": {"running_since": date_to_bson(&started_at)?}},
+        )
+        .await?
+        .modified_count
+        > 0;
+    if !is_claimed {
+        return Ok(None);
     }
 
-    Ok(())
+    tracing::info!("Running {} at {}", job, started_at);
+
+    let timer = Instant::now();
+    let result = match job {
+        CronJob::FileSweep => jobs::sweep_files(&app_state).await,
+        CronJob::TokenCleanup => jobs::delete_expired_tokens(&app_state).await,
+        CronJob::UploadSessionCleanup => jobs::delete_expired_upload_sessions(&app_state).await,
+    };
+
+    let (outcome, error, items) = match result {
+        Ok(items) => (JobOutcome::Succeeded, None, items),
+        Err(e) => {
+            tracing::error!("Error in {}: {:?}", job, e);
+            (JobOutcome::Failed, Some(e.to_string()), JobItems::new())
+        }
+    };
+
+    let run = JobRun {
+        trigger,
+        started_at,
+        duration_ms: timer.elapsed().as_millis() as u64,
+        outcome,
+        error,
+        items,
+    };
+
+    let run_bson = bson::to_bson(&run)
+        .map_err(|_| AppError::Internal("Failed to serialize job run".to_string()))?;
+    let mut set = doc! {"last_run": &run_bson};
+    if outcome == JobOutcome::Failed {
+        set.insert("last_failure", run_bson);
+    }
+
+    app_state
+        .cron_job_collection
+        .update_one(
+            doc! {"_id": job.to_string()},
+            doc! {"$set": set, "$unset": {"running_since": ""}},
+        )
+        .await?;
+
+    Ok(Some(run))
 }
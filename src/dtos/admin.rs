@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::cron_job::{CronJob, CronJobCollection, JobRun};
+
+/// A background job as shown to admins.
+#[derive(Serialize)]
+pub struct CronJobView {
+    pub job: CronJob,
+    pub schedule: String,
+    pub is_paused: bool,
+    pub running_since: Option<DateTime<Utc>>,
+    pub last_run: Option<JobRun>,
+    pub last_failure: Option<JobRun>,
+}
+
+impl From<CronJobCollection> for CronJobView {
+    fn from(cron_job: CronJobCollection) -> Self {
+        CronJobView {
+            job: cron_job.job,
+            schedule: cron_job.schedule,
+            is_paused: cron_job.is_paused,
+            running_since: cron_job.running_since,
+            last_run: cron_job.last_run,
+            last_failure: cron_job.last_failure,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CronJobsResponse {
+    pub jobs: Vec<CronJobView>,
+}
+
+#[derive(Serialize)]
+pub struct CronJobResponse {
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct RunCronJobResponse {
+    pub run: JobRun,
+}
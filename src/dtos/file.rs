@@ -1,10 +1,31 @@
-use axum::body::Bytes;
-use chrono::{DateTime, Utc};
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use validator::{Validate, ValidationError};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::{
+    error::AppError,
+    models::{
+        download::{DownloadCollection, DownloadStatus, UserAgentFamily},
+        file::{BundleMember, EncryptionMode, FileCollection, FileStatus, KeySlot, ShredKey},
+        share_link::ShareLinkCollection,
+    },
+    utils::{misc::object_id_to_str, pagination::SortOrder},
+};
+
+/// Largest file (in bytes) that can be uploaded.
+pub const MAX_UPLOAD_SIZE: u64 = 100_000_000; // 100 MB
 
-use crate::models::file::FileCollection;
+/// Most files a bundle can have.
+pub const MAX_BUNDLE_MEMBERS: usize = 100;
+
+/// How far in the future a file (or share link) can expire.
+pub const MAX_EXPIRY_DAYS: i64 = 365;
+
+/// Name stored for client-side encrypted files uploaded without one.
+pub const ENCRYPTED_FILE_NAME: &str = "encrypted.bin";
 
 #[derive(Debug, Clone, Validate)]
 pub struct UploadFileRequest {
@@ -13,29 +34,174 @@ pub struct UploadFileRequest {
 
     pub user_id: ObjectId,
 
-    pub file_data: Bytes,
-
-    #[validate(range(
-        exclusive_min = 0,
-        max = 10_000_000,
-        message = "size should be less than 10 MB"
-    ))]
+    // Checked with `validate_upload_size` while the file streams in
     pub size: u64, // bytes
     pub cid: String,
     pub mime_type: String,
-    pub password: String,
+    // Without one, the file gets a random share key
+    pub password: Option<String>,
 
     #[validate(custom(function = "validate_expires_at"))]
     pub expires_at: DateTime<Utc>,
 
     #[validate(range(exclusive_min = 0, max = 10, message = "expected between 1 to 10"))]
     pub max_downloads: u8,
+
+    pub hide_metadata: bool,
+
+    pub encryption_mode: EncryptionMode,
+
+    #[validate(
+        length(max = 4096, message = "metadata should be at most 4096 characters"),
+        custom(function = "validate_client_metadata")
+    )]
+    pub client_metadata: Option<String>,
+
+    pub key_slots: Vec<KeySlot>,
+    pub shred_key: Option<ShredKey>,
+
+    // The files of a bundle; empty for a single file
+    pub members: Vec<BundleMember>,
+}
+
+/// Client metadata is returned in a response header, so it has to be base64 (or base64url).
+fn validate_client_metadata(metadata: &str) -> Result<(), ValidationError> {
+    if !metadata
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "+/=-_".contains(c))
+    {
+        return Err(ValidationError::new("`metadata` must_be_base64"));
+    }
+    Ok(())
 }
 
 fn validate_expires_at(date: &DateTime<Utc>) -> Result<(), ValidationError> {
-    if *date <= Utc::now() {
+    let now = Utc::now();
+    if *date <= now {
         return Err(ValidationError::new("`expires_at` must_be_in_future"));
     }
+    if *date > now + Duration::days(MAX_EXPIRY_DAYS) {
+        return Err(ValidationError::new("`expires_at` must_be_within_a_year"));
+    }
+    Ok(())
+}
+
+/// Checks the size of an uploaded file, which is only known once it has been streamed.
+///
+/// # Arguments
+/// * `size` - The number of bytes received so far.
+///
+/// # Returns
+/// * `Ok(())` if `0 < size <= MAX_UPLOAD_SIZE`.
+/// * `Err(ValidationErrors)` with a `size` error otherwise.
+pub fn validate_upload_size(size: u64) -> Result<(), ValidationErrors> {
+    if size == 0 || size > MAX_UPLOAD_SIZE {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "size",
+            ValidationError::new("range").with_message("size should be less than 100 MB".into()),
+        );
+        return Err(errors);
+    }
+    Ok(())
+}
+
+/// Turns the name a bundle member was uploaded with into its path within the bundle.
+///
+/// Backslashes are read as separators, and empty and `.` segments are dropped.
+///
+/// # Returns
+/// * `Ok(String)` with the normalized relative path, e.g. `docs/report.pdf`.
+/// * `Err(AppError::BadRequest)` if the path is empty, too long or leaves the bundle (`..`).
+pub fn bundle_member_path(name: &str) -> Result<String, AppError> {
+    let segments = name
+        .split(['/', '\\'])
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>();
+
+    if segments.is_empty() || segments.contains(&"..") {
+        return Err(AppError::BadRequest(format!(
+            "Invalid path in bundle: `{}`",
+            name
+        )));
+    }
+
+    let path = segments.join("/");
+    if path.len() > 1024 {
+        return Err(AppError::BadRequest(
+            "Path in bundle is too long".to_string(),
+        ));
+    }
+
+    Ok(path)
+}
+
+/// Removes control characters (e.g. line breaks) from a file name, which can't be
+/// sent back in a `Content-Disposition` header.
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Keeps a client-supplied MIME type only if it is a well-formed `type/subtype` (with
+/// optional parameters), since it is sent back in the `Content-Type` of downloads.
+///
+/// # Returns
+/// * `String` with the MIME type, or `application/octet-stream` if it is malformed.
+pub fn sanitize_mime_type(mime_type: &str) -> String {
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+
+    let (essence, params) = mime_type.split_once(';').unwrap_or((mime_type, ""));
+    let is_valid = essence
+        .trim()
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype))
+        && params.chars().all(|c| c == ' ' || c.is_ascii_graphic());
+
+    match is_valid {
+        true => mime_type.trim().to_string(),
+        false => "application/octet-stream".to_string(),
+    }
+}
+
+/// Checks the files of a bundle once they have all been streamed.
+///
+/// # Returns
+/// * `Ok(())` if there are at most `MAX_BUNDLE_MEMBERS` files with distinct paths,
+///   together no larger than `MAX_UPLOAD_SIZE`.
+/// * `Err(ValidationErrors)` with a `members` error otherwise.
+pub fn validate_bundle(members: &[BundleMember]) -> Result<(), ValidationErrors> {
+    let mut paths = members
+        .iter()
+        .map(|member| &member.path)
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    let message = if members.len() > MAX_BUNDLE_MEMBERS {
+        Some(format!(
+            "a bundle can have at most {} files",
+            MAX_BUNDLE_MEMBERS
+        ))
+    } else if paths.len() < members.len() {
+        Some("paths in a bundle must be unique".to_string())
+    } else if members.iter().map(|member| member.size).sum::<u64>() > MAX_UPLOAD_SIZE {
+        Some("bundle should be less than 100 MB".to_string())
+    } else {
+        None
+    };
+
+    if let Some(message) = message {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "members",
+            ValidationError::new("bundle").with_message(message.into()),
+        );
+        return Err(errors);
+    }
     Ok(())
 }
 
@@ -46,11 +212,16 @@ impl Default for UploadFileRequest {
             file_name: Default::default(),
             expires_at: Default::default(),
             max_downloads: Default::default(),
+            hide_metadata: false,
             size: 0,
-            cid: format!("cid"),
-            mime_type: format!("mime_type"),
-            password: "default-password".to_string(),
-            file_data: Bytes::new(),
+            cid: "cid".to_string(),
+            mime_type: "mime_type".to_string(),
+            password: None,
+            encryption_mode: EncryptionMode::Server,
+            client_metadata: None,
+            key_slots: vec![],
+            shred_key: None,
+            members: vec![],
         }
     }
 }
@@ -59,15 +230,391 @@ impl Default for UploadFileRequest {
 pub struct UploadFileResponse {
     pub id: String,
     pub message: String,
+    // What recipients use to find the file, short and as words
+    pub code: String,
+    pub words: String,
+    // Share key of a file uploaded without a password; only ever returned here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub link: String,
 }
 
 #[derive(Deserialize)]
 pub struct DownloadFileRequest {
-    pub file_id: String,
+    // Either the file (by its share code, or the id of an older file) or one of its share links
+    pub code: Option<String>,
+    pub file_id: Option<String>,
+    pub token: Option<String>,
+    // May also be sent as the password of an `Authorization: Basic` header
     pub password: Option<String>,
+    // Share key of a file uploaded without a password
+    pub key: Option<String>,
+    // Path of one file of a bundle; without one a bundle is downloaded as a ZIP
+    pub member: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DirectDownloadPath {
+    // The share code of the file or the token of one of its share links
+    pub share: String,
+    // The path of one file of a bundle; for single files it only names the download
+    pub file_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DownloadTicketResponse {
+    pub ticket: String,
+    // GET it to download the file
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateFileRequest {
+    #[validate(length(min = 1, message = "Name cannot be empty"))]
+    pub file_name: Option<String>,
+
+    #[validate(custom(function = "validate_expires_at"))]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[validate(range(exclusive_min = 0, max = 10, message = "expected between 1 to 10"))]
+    pub max_downloads: Option<u8>,
+
+    pub hide_metadata: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct UpdateFileResponse {
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct DeleteFileResponse {
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct RevokeFileResponse {
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct FileInfoResponse {
+    // No id: it would let anyone with a share code learn the enumerable ObjectId
+    // `None` if the owner hid them
+    pub name: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: u64, // bytes
+    pub expires_at: DateTime<Utc>,
+    pub downloads_remaining: u8,
+    pub password_required: bool,
+    pub encryption_mode: EncryptionMode,
+    pub metadata: Option<String>,
+    // The files of a bundle; `None` for single files or if the owner hid them
+    pub members: Option<Vec<BundleMemberView>>,
+    pub status: FileStatus,
+    // Whether the file can still be downloaded by its share code
+    pub is_available: bool,
+}
+
+#[derive(Serialize)]
+pub struct BundleMemberView {
+    pub path: String,
+    pub size: u64, // bytes
+}
+
+#[derive(Deserialize, Validate)]
+pub struct FilePasswordRequest {
+    pub current_password: String,
+
+    #[validate(length(min = 1, message = "Password cannot be empty"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveFilePasswordRequest {
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct FilePasswordResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UserFilesRequest {
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+
+    #[validate(range(min = 1, max = 100, message = "expected between 1 to 100"))]
+    pub limit: Option<u32>,
+
+    pub status: Option<FileStatus>,
+    // Exact type (`image/png`) or a prefix ending in `/` (`image/`)
+    pub mime_type: Option<String>,
+    // Case-insensitive part of the name
+    pub name: Option<String>,
+    pub uploaded_after: Option<DateTime<Utc>>,
+    pub uploaded_before: Option<DateTime<Utc>>,
+
+    pub sort_by: Option<FileSortField>,
+    pub order: Option<SortOrder>,
+}
+
+/// Fields the user file listing can be sorted by.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSortField {
+    #[default]
+    UploadedAt,
+    ExpiresAt,
+    Name,
+    Size,
+}
+
+impl FileSortField {
+    /// Name of the field in `FileCollection`.
+    pub fn field(self) -> &'static str {
+        match self {
+            FileSortField::UploadedAt => "uploaded_at",
+            FileSortField::ExpiresAt => "expires_at",
+            FileSortField::Name => "name",
+            FileSortField::Size => "size",
+        }
+    }
+}
+
+/// What owners get to see of a file: no storage identifiers, keys or recipient IPs.
+#[derive(Serialize)]
+pub struct FileView {
+    pub id: String,
+    pub share_code: Option<String>,
+    pub name: String,
+    pub size: u64, // bytes
+    pub mime_type: String,
+    pub uploaded_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: u8,
+    pub download_count: u8,
+    pub downloads_remaining: u8,
+    pub status: FileStatus,
+    pub password_protected: bool,
+    pub hide_metadata: bool,
+    pub encryption_mode: EncryptionMode,
+    pub metadata: Option<String>,
+}
+
+impl TryFrom<FileCollection> for FileView {
+    type Error = AppError;
+
+    fn try_from(file: FileCollection) -> Result<Self, Self::Error> {
+        Ok(FileView {
+            id: object_id_to_str(&file.id)?,
+            share_code: file.share_code.clone(),
+            status: file.status(),
+            downloads_remaining: file.max_downloads.saturating_sub(file.download_count),
+            name: file.name,
+            size: file.size,
+            mime_type: file.mime_type,
+            uploaded_at: file.uploaded_at,
+            expires_at: file.expires_at,
+            max_downloads: file.max_downloads,
+            download_count: file.download_count,
+            password_protected: file.password_protected,
+            hide_metadata: file.hide_metadata,
+            encryption_mode: file.encryption_mode,
+            metadata: file.client_metadata,
+        })
+    }
 }
 
 #[derive(Serialize)]
 pub struct UserFilesResponse {
-    pub files: Vec<FileCollection>,
+    pub files: Vec<FileView>,
+    // Pass as `cursor` to get the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct FileDownloadsRequest {
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+
+    #[validate(range(min = 1, max = 100, message = "expected between 1 to 100"))]
+    pub limit: Option<u32>,
+
+    pub status: Option<DownloadStatus>,
+}
+
+/// One entry of a file's download history, as shown to its owner.
+#[derive(Serialize)]
+pub struct DownloadView {
+    pub id: String,
+    pub share_link_id: Option<String>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub downloaded_at: DateTime<Utc>,
+    pub status: DownloadStatus,
+    pub bytes_sent: u64,
+    pub duration_ms: Option<u64>,
+    pub is_resumed: bool,
+}
+
+impl TryFrom<DownloadCollection> for DownloadView {
+    type Error = AppError;
+
+    fn try_from(download: DownloadCollection) -> Result<Self, Self::Error> {
+        Ok(DownloadView {
+            id: object_id_to_str(&download.id)?,
+            share_link_id: download.share_link_id.map(|id| id.to_hex()),
+            ip_address: download.ip_address,
+            user_agent: download.user_agent,
+            downloaded_at: download.downloaded_at,
+            status: download.status,
+            bytes_sent: download.bytes_sent,
+            duration_ms: download.duration_ms,
+            is_resumed: download.is_resumed,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct FileDownloadsResponse {
+    pub downloads: Vec<DownloadView>,
+    // Pass as `cursor` to get the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DailyDownloads {
+    pub date: NaiveDate, // UTC
+    pub downloads: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserAgentDownloads {
+    pub family: UserAgentFamily,
+    pub downloads: u64,
+}
+
+/// Download summary of a file or of all files of a user.
+///
+/// Resumed downloads count as attempts (`completed`, `failed`) but not as downloads.
+#[derive(Serialize)]
+pub struct DownloadStatsResponse {
+    pub downloads: u64,
+    pub unique_ips: u64,
+    pub per_day: Vec<DailyDownloads>,
+    pub user_agents: Vec<UserAgentDownloads>,
+    pub completed: u64,
+    pub failed: u64,
+    // For a user: the average over the files downloaded at least once
+    pub time_to_first_download_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateShareLinkRequest {
+    #[validate(length(min = 1, max = 100, message = "expected between 1 to 100 characters"))]
+    pub label: Option<String>,
+
+    // Defaults to the expiry of the file, which it can not exceed
+    #[validate(custom(function = "validate_expires_at"))]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    #[validate(range(exclusive_min = 0, max = 10, message = "expected between 1 to 10"))]
+    pub max_downloads: u8,
+
+    // Required on top of the token if given
+    #[validate(length(min = 1, message = "Password cannot be empty"))]
+    pub password: Option<String>,
+
+    // A password (or the share key) of the file, to unlock its data key
+    pub current_password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateShareLinkResponse {
+    pub id: String,
+    pub message: String,
+    // Only ever returned here
+    pub token: String,
+    pub link: String,
+}
+
+/// A share link as shown to the owner of its file.
+#[derive(Serialize)]
+pub struct ShareLinkView {
+    pub id: String,
+    pub label: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: u8,
+    pub downloads_remaining: u8,
+    pub password_protected: bool,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    // Whether the link can still be used
+    pub is_available: bool,
+}
+
+impl TryFrom<ShareLinkCollection> for ShareLinkView {
+    type Error = AppError;
+
+    fn try_from(share_link: ShareLinkCollection) -> Result<Self, Self::Error> {
+        Ok(ShareLinkView {
+            id: object_id_to_str(&share_link.id)?,
+            is_available: share_link.is_available(),
+            label: share_link.label,
+            expires_at: share_link.expires_at,
+            max_downloads: share_link.max_downloads,
+            downloads_remaining: share_link
+                .max_downloads
+                .saturating_sub(share_link.download_count),
+            password_protected: share_link.password_protected,
+            created_at: share_link.created_at,
+            revoked_at: share_link.revoked_at,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct ShareLinksResponse {
+    pub links: Vec<ShareLinkView>,
+}
+
+#[derive(Serialize)]
+pub struct RevokeShareLinkResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CreateSignedUrlRequest {
+    // Seconds until the URL expires; at most a week, and never after the file
+    #[validate(range(min = 60, max = 604800, message = "expected between 60 and 604800"))]
+    pub expires_in: Option<i64>,
+
+    // Binds the URL to one client
+    pub ip: Option<IpAddr>,
+
+    // A password (or the share key) of the file, to unlock its data key
+    pub current_password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateSignedUrlResponse {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct SignedDownloadRequest {
+    pub expires: i64,
+    pub ip: Option<IpAddr>,
+    pub v: u32,
+    pub key: Option<String>,
+    pub sig: String,
+}
+
+#[derive(Serialize)]
+pub struct RevokeSignedUrlsResponse {
+    pub message: String,
 }
@@ -21,6 +21,12 @@ pub enum AppError {
     #[error("BadRequest: {0}")]
     BadRequest(String),
 
+    #[error("NotFound: {0}")]
+    NotFound(String),
+
+    #[error("Gone: {0}")]
+    Gone(String),
+
     #[error("Hashing: {0}")]
     Hashing(String),
 
@@ -61,6 +67,14 @@ impl IntoResponse for AppError {
 
                 (StatusCode::BAD_REQUEST, error)
             }
+            AppError::NotFound(e) => {
+                let error = ErrorResponse {
+                    kind: "NotFound".to_string(),
+                    message: e,
+                };
+
+                (StatusCode::NOT_FOUND, error)
+            }
             AppError::Jwt(e) => {
                 let error = ErrorResponse {
                     kind: "JWT".to_string(),
@@ -69,6 +83,14 @@ impl IntoResponse for AppError {
 
                 (StatusCode::INTERNAL_SERVER_ERROR, error)
             }
+            AppError::Gone(e) => {
+                let error = ErrorResponse {
+                    kind: "Gone".to_string(),
+                    message: e,
+                };
+
+                (StatusCode::GONE, error)
+            }
             AppError::Unauthorized(e) => {
                 let error = ErrorResponse {
                     kind: "Unauthorized".to_string(),
@@ -0,0 +1,159 @@
+use axum::{extract::Path, response::IntoResponse, Extension, Json};
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use reqwest::StatusCode;
+
+use crate::{
+    config::AppState,
+    cron::run_job,
+    dtos::admin::{CronJobResponse, CronJobView, CronJobsResponse, RunCronJobResponse},
+    error::AppError,
+    models::cron_job::{CronJob, JobTrigger},
+};
+
+/// Lists the background jobs with their schedule, state and last runs (admin only).
+///
+/// # Parameters
+/// - `app_state`: Shared application state with DB references.
+///
+/// # Returns
+/// - `200 OK` with the `CronJobView` of every job.
+///
+/// # Example
+/// ```http
+/// GET /admin/jobs
+/// X-Admin-Key: 5f2b...
+/// ```
+pub async fn cron_jobs(
+    Extension(app_state): Extension<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let jobs = app_state
+        .cron_job_collection
+        .find(doc! {})
+        .sort(doc! {"_id": 1})
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in fetching jobs{}", e)))?
+        .into_iter()
+        .map(CronJobView::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(CronJobsResponse { jobs })))
+}
+
+/// Stops a job from running on its schedule until it is resumed (admin only).
+///
+/// A run that already started is not interrupted, and the job can still be triggered.
+///
+/// # Parameters
+/// - `app_state`: Shared application state with DB references.
+/// - `job`: The name of the job, e.g. `file_sweep`.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::NotFound` if there is no such job.
+///
+/// # Example
+/// ```http
+/// POST /admin/jobs/file_sweep/pause
+/// X-Admin-Key: 5f2b...
+/// ```
+pub async fn pause_cron_job(
+    Extension(app_state): Extension<AppState>,
+    Path(job): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    set_paused(&app_state, &job, true).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CronJobResponse {
+            message: "Job paused successfully".to_string(),
+        }),
+    ))
+}
+
+/// Lets a paused job run on its schedule again (admin only).
+///
+/// # Parameters
+/// - `app_state`: Shared application state with DB references.
+/// - `job`: The name of the job, e.g. `file_sweep`.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::NotFound` if there is no such job.
+///
+/// # Example
+/// ```http
+/// POST /admin/jobs/file_sweep/resume
+/// X-Admin-Key: 5f2b...
+/// ```
+pub async fn resume_cron_job(
+    Extension(app_state): Extension<AppState>,
+    Path(job): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    set_paused(&app_state, &job, false).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CronJobResponse {
+            message: "Job resumed successfully".to_string(),
+        }),
+    ))
+}
+
+/// Runs a job right away, paused or not, and waits for it to finish (admin only).
+///
+/// The run is recorded like a scheduled one, and finishes even if the client goes away.
+///
+/// # Parameters
+/// - `app_state`: Shared application state with DB references.
+/// - `job`: The name of the job, e.g. `file_sweep`.
+///
+/// # Returns
+/// - `200 OK` with the `run`: its duration, outcome and item counts.
+/// - `AppError::BadRequest` if the job is already running.
+/// - `AppError::NotFound` if there is no such job.
+///
+/// # Example
+/// ```http
+/// POST /admin/jobs/file_sweep/run
+/// X-Admin-Key: 5f2b...
+/// ```
+pub async fn run_cron_job(
+    Extension(app_state): Extension<AppState>,
+    Path(job): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = parse_job(&job)?;
+
+    let run = tokio::spawn(run_job(app_state, job, JobTrigger::Manual))
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in running job: {}", e)))??
+        .ok_or_else(|| AppError::BadRequest("Job is already running".to_string()))?;
+
+    Ok((StatusCode::OK, Json(RunCronJobResponse { run })))
+}
+
+/// Pauses or resumes a job.
+async fn set_paused(app_state: &AppState, job: &str, is_paused: bool) -> Result<(), AppError> {
+    let job = parse_job(job)?;
+
+    let update_result = app_state
+        .cron_job_collection
+        .update_one(
+            doc! {"_id": job.to_string()},
+            doc! {"$set": {"is_paused": is_paused}},
+        )
+        .await?;
+
+    if update_result.matched_count == 0 {
+        return Err(AppError::NotFound("No such job exists!".to_string()));
+    }
+
+    Ok(())
+}
+
+fn parse_job(job: &str) -> Result<CronJob, AppError> {
+    job.parse()
+        .map_err(|_| AppError::NotFound("No such job exists!".to_string()))
+}
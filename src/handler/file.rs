@@ -1,42 +1,95 @@
-use std::net::SocketAddr;
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
 
 use axum::{
-    body::Body,
-    extract::{ConnectInfo, Multipart, Query},
-    http::{HeaderMap, HeaderValue, Response},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Multipart, Path, Query},
+    http::{header, HeaderMap, HeaderValue, Response},
     response::IntoResponse,
     Extension, Json,
 };
-use chrono::{DateTime, Utc};
-use futures::TryStreamExt;
-use mongodb::bson::{self, doc};
+use chrono::{DateTime, Duration, Utc};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId},
+    options::ReturnDocument,
+};
 use reqwest::StatusCode;
+use tokio::runtime::Handle;
 use validator::Validate;
 
 use crate::{
     config::AppState,
-    dtos::file::{DownloadFileRequest, UploadFileRequest, UploadFileResponse, UserFilesResponse},
+    dtos::file::{
+        bundle_member_path, sanitize_file_name, validate_bundle, validate_upload_size,
+        BundleMemberView, DeleteFileResponse, DirectDownloadPath, DownloadFileRequest,
+        DownloadTicketResponse, DownloadView, FileDownloadsRequest, FileDownloadsResponse,
+        FileInfoResponse, FilePasswordRequest, FilePasswordResponse, FileView,
+        RemoveFilePasswordRequest, UpdateFileRequest, UpdateFileResponse, UploadFileRequest,
+        UploadFileResponse, UserFilesRequest, UserFilesResponse, ENCRYPTED_FILE_NAME,
+    },
     error::AppError,
-    models::file::{DownloadEntry, FileCollection},
+    handler::{
+        lifecycle::{
+            purge_file, record_status, set_file_status, status_filter, stored_status_filter,
+        },
+        upload::delete_parts,
+    },
+    models::{
+        download::{download_session_start, DownloadCollection, DownloadStatus},
+        download_ticket::{DownloadTicketCollection, DOWNLOAD_TICKET_SECONDS},
+        file::{
+            is_password_protected, BundleMember, EncryptionMode, FileCollection, FileStatus,
+            KeySlot,
+        },
+        share_link::ShareLinkCollection,
+    },
+    storage::{read_range, BlobStream},
     utils::{
-        cloudinary::{read_file_from_cloud, upload_file_to_cloud},
-        extractor::ExtractAuthAgent,
-        file::{decrypt_file_with_password, encrypt_file_with_password},
-        misc::{object_id_to_str, str_to_object_id},
+        envelope::{
+            content_key, generate_data_key, generate_share_key, generate_shred_key,
+            open_with_ticket, seal_with_ticket, unwrap_content_key, unwrap_data_key, wrap_data_key,
+            wrap_new_data_key, MAX_KEY_SLOTS,
+        },
+        extractor::{ExtractAuthAgent, ExtractBasicAuth},
+        file::{decrypt_range, encrypt_stream, FileKey},
+        hashing::hash_token,
+        misc::{
+            content_disposition, date_to_bson, escape_regex, object_id_to_str, str_to_object_id,
+        },
+        pagination::{sort_by, Cursor, SortOrder, DEFAULT_PAGE_SIZE},
+        range::{resolve_range, RangeRequest},
+        share_code::{share_code_filter, share_code_words},
+        zip::{zip_size, zip_stream, ZipEntry},
     },
 };
 
 /// Handles authenticated file uploads via multipart/form-data.
 ///
 /// Accepts the following fields:
-/// - `file` (required): The file to be uploaded.
-/// - `file_name`: A user-defined name for the file.
-/// - `password` (required): Used to encrypt the file before storage.
+/// - `file` (required): The file to be uploaded. Must be the last field; several `file`
+///   fields make a bundle, with each file's path taken from its file name.
+/// - `file_name`: A user-defined name for the file (or bundle).
+/// - `password` (optional): Protects the file; without one a random share key is generated.
 /// - `expires_at`: ISO datetime for file expiration.
 /// - `max_downloads` (optional): Max number of allowed downloads.
+/// - `hide_metadata` (optional): `true` hides the name and MIME type from the info endpoint.
+/// - `encryption_mode` (optional): `server` (default) or `client`.
+/// - `metadata` (optional): Opaque, base64 encoded metadata of a `client` mode file.
+///
+/// The other fields are validated as soon as the first `file` field starts, then each
+/// file is streamed through encryption into storage without being buffered as a whole.
+/// In `client` mode the file is already encrypted by the client and is stored as-is;
+/// `password` is ignored, `file_name` is optional and bundles are not supported.
+/// Metadata is saved to MongoDB. Returns a file ID on success.
 ///
-/// The file is encrypted using the provided password, uploaded to storage,
-/// and metadata is saved to MongoDB. Returns a file ID on success.
+/// The files of a bundle (relative paths such as `docs/report.pdf` keep folder uploads
+/// apart) share one key, expiry, download limit and password; recipients download them
+/// one by one or as a ZIP.
 ///
 /// # Parameters
 /// - `agent`: Authenticated user context.
@@ -44,18 +97,80 @@ use crate::{
 /// - `multipart`: Incoming multipart form data.
 ///
 /// # Returns
-/// - `201 Created` with JSON `{ message, id }` on success.
+/// - `201 Created` with JSON `{ message, id, code, words, link }` on success, plus the
+///   `key` of a file uploaded without a password.
 /// - `AppError` variants for validation, parsing, encryption, or DB errors.
 ///
 /// # Security
-/// File contents are encrypted at upload; passwords are not stored.
+/// File contents are encrypted at upload (or by the client); passwords are not stored.
+/// Files without a password get a random share key, which is returned only once.
 pub async fn upload_file(
     agent: ExtractAuthAgent,
     Extension(app_state): Extension<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
-    let mut upload_file_request = UploadFileRequest::default();
-    upload_file_request.user_id = agent.user_id;
+    let mut upload_file_request = UploadFileRequest {
+        user_id: agent.user_id,
+        ..Default::default()
+    };
+    let mut members = Vec::new();
+
+    let share_key = match read_upload_form(
+        &app_state,
+        &mut multipart,
+        &mut upload_file_request,
+        &mut members,
+    )
+    .await
+    {
+        Ok(share_key) => share_key,
+        Err(e) => {
+            // Nothing of a failed upload is kept
+            let cids = members
+                .into_iter()
+                .map(|member| member.cid)
+                .collect::<Vec<_>>();
+            if let Err(delete_error) = delete_parts(&app_state, &cids).await {
+                tracing::error!(
+                    "Error removing files of a failed upload: {:?}",
+                    delete_error
+                );
+            }
+            return Err(e);
+        }
+    };
+
+    let (id, share_code) = save_file(&app_state, upload_file_request).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadFileResponse {
+            message: "File uploaded successfully".to_string(),
+            link: share_link(&share_code, share_key.as_deref()),
+            words: share_code_words(&share_code)?,
+            code: share_code,
+            key: share_key,
+            id,
+        }),
+    ))
+}
+
+/// Reads the form of `upload_file`, storing each `file` field as it arrives.
+///
+/// # Parameters
+/// - `upload_file_request`: Filled in from the form.
+/// - `members`: Every file stored so far, so the caller can remove them on failure.
+///
+/// # Returns
+/// - `Ok(Option<String>)` with the generated share key of an upload without a password.
+async fn read_upload_form(
+    app_state: &AppState,
+    multipart: &mut Multipart,
+    upload_file_request: &mut UploadFileRequest,
+    members: &mut Vec<BundleMember>,
+) -> Result<Option<String>, AppError> {
+    // The keys of the upload, made when its first file arrives
+    let mut upload_keys = None;
 
     while let Some(field) = multipart
         .next_field()
@@ -67,6 +182,13 @@ pub async fn upload_file(
             .map(str::to_string)
             .ok_or_else(|| AppError::Internal("Error reading field name".to_string()))?;
 
+        // The files were encrypted with what was known when they arrived
+        if !members.is_empty() && form_key != "file" {
+            return Err(AppError::BadRequest(
+                "`file` must be the last field of the form".to_string(),
+            ));
+        }
+
         match form_key.as_str() {
             "password" => {
                 let text = field
@@ -74,7 +196,7 @@ pub async fn upload_file(
                     .await
                     .map_err(|e| AppError::Internal(format!("Error reading text: {}", e)))?;
 
-                upload_file_request.password = text;
+                upload_file_request.password = Some(text).filter(|text| !text.is_empty());
             }
             "expires_at" => {
                 let text = field
@@ -104,194 +226,1965 @@ pub async fn upload_file(
 
                 upload_file_request.file_name = text;
             }
+            "hide_metadata" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Error reading text: {}", e)))?;
+
+                upload_file_request.hide_metadata = text.parse::<bool>().map_err(|e| {
+                    AppError::Internal(format!("Error parsing hide_metadata: {}", e))
+                })?;
+            }
+            "encryption_mode" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Error reading text: {}", e)))?;
+
+                upload_file_request.encryption_mode =
+                    text.parse::<EncryptionMode>().map_err(|_| {
+                        AppError::BadRequest(
+                            "`encryption_mode` should be either `server` or `client`".to_string(),
+                        )
+                    })?;
+            }
+            "metadata" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Error reading text: {}", e)))?;
+
+                upload_file_request.client_metadata = Some(text);
+            }
             "file" => {
                 let content_type = field
                     .content_type()
                     .map(|ct| ct.to_string())
-                    .ok_or_else(|| AppError::Internal(format!("Error reading file type")))?;
+                    .ok_or_else(|| AppError::Internal("Error reading file type".to_string()))?;
+                // Only used as the file's path if it turns out to be part of a bundle
+                let path = field.file_name().map(str::to_string).unwrap_or_default();
 
-                // Read the file bytes (consumes field here)
-                let file_data = field
-                    .bytes()
-                    .await
-                    .map_err(|e| AppError::Internal(format!("Error reading file bytes: {}", e)))?;
+                let is_client_encrypted =
+                    upload_file_request.encryption_mode == EncryptionMode::Client;
 
-                upload_file_request.size = file_data.len() as u64;
-                upload_file_request.mime_type = content_type;
-                upload_file_request.file_data = file_data;
+                if is_client_encrypted && !members.is_empty() {
+                    return Err(AppError::BadRequest(
+                        "Client-side encrypted files can not be bundled".to_string(),
+                    ));
+                }
+
+                if is_client_encrypted && upload_file_request.file_name.is_empty() {
+                    upload_file_request.file_name = ENCRYPTED_FILE_NAME.to_string();
+                }
+
+                let (content_key, _) = match &upload_keys {
+                    Some(upload_keys) => upload_keys,
+                    None => {
+                        upload_keys.insert(prepare_upload(app_state, upload_file_request).await?)
+                    }
+                };
+
+                let (cid, size) = store_blob(
+                    app_state,
+                    &upload_file_request.file_name,
+                    content_key.as_ref(),
+                    field,
+                )
+                .await?;
+
+                members.push(BundleMember {
+                    path,
+                    size,
+                    cid,
+                    // The real type of a client-side encrypted file is part of its metadata
+                    mime_type: if is_client_encrypted {
+                        "application/octet-stream".to_string()
+                    } else {
+                        content_type
+                    },
+                });
             }
             _ => {}
         }
     }
 
+    match members.as_mut_slice() {
+        [] => {
+            return Err(AppError::BadRequest("No `file` field given".to_string()));
+        }
+        [member] => {
+            upload_file_request.cid = member.cid.clone();
+            upload_file_request.size = member.size;
+            upload_file_request.mime_type = member.mime_type.clone();
+        }
+        members => {
+            for member in members.iter_mut() {
+                member.path = bundle_member_path(&member.path)?;
+            }
+            validate_bundle(members)?;
+
+            upload_file_request.size = members.iter().map(|member| member.size).sum();
+            upload_file_request.mime_type = "application/zip".to_string();
+            upload_file_request.members = members.to_vec();
+        }
+    }
+
+    Ok(upload_keys.and_then(|(_, share_key)| share_key))
+}
+
+/// Builds the download link of a file, with its share key (if it has one) in the fragment.
+///
+/// Fragments are never sent to the server, so the key stays out of request lines, logs
+/// and `Referer` headers; clients send it as the HTTP Basic password instead.
+pub fn share_link(share_code: &str, share_key: Option<&str>) -> String {
+    match share_key {
+        Some(share_key) => format!("/d/{}#{}", share_code, share_key),
+        None => format!("/d/{}", share_code),
+    }
+}
+
+/// Validates an upload and gives it its keys, unless the client encrypts it.
+///
+/// The file gets its own data key and shred key; the password (or else a random share
+/// key) only wraps the data key, into the key slot set on `upload_file_request`.
+///
+/// # Returns
+/// - `Ok((Option<[u8; 32]>, Option<String>))` with the key to encrypt the upload with
+///   (`None` in `client` mode) and the generated share key of an upload without a password.
+/// - `AppError` variants for validation or key errors.
+pub async fn prepare_upload(
+    app_state: &AppState,
+    upload_file_request: &mut UploadFileRequest,
+) -> Result<(Option<[u8; 32]>, Option<String>), AppError> {
     if let Err(errors) = upload_file_request.validate() {
         return Err(AppError::Validation(errors));
     }
 
-    let encrypted_file = encrypt_file_with_password(
-        upload_file_request.file_data.to_vec(),
-        &upload_file_request.password,
-    )?;
+    if upload_file_request.encryption_mode == EncryptionMode::Client {
+        return Ok((None, None));
+    }
+
+    let data_key = generate_data_key();
+    let (shred_key, wrapped_shred_key) =
+        generate_shred_key(app_state.key_provider.as_ref()).await?;
+    let (key_slot, share_key) =
+        wrap_new_data_key(&data_key, upload_file_request.password.as_deref())?;
+    upload_file_request.key_slots = vec![key_slot];
+    upload_file_request.shred_key = Some(wrapped_shred_key);
+
+    Ok((Some(content_key(&data_key, Some(&shred_key))), share_key))
+}
+
+/// Streams one uploaded file through encryption into storage.
+///
+/// # Parameters
+/// - `name`: The name the blob is stored under.
+/// - `content_key`: The key from `prepare_upload`; `None` stores the file as it is.
+/// - `file`: The bytes of the file.
+///
+/// # Returns
+/// - `Ok((String, u64))` with the `cid` and the size of the file; the blob is removed
+///   again if the file turns out empty.
+/// - `AppError` variants for validation, encryption, or storage errors.
+pub async fn store_blob<S, E>(
+    app_state: &AppState,
+    name: &str,
+    content_key: Option<&[u8; 32]>,
+    file: S,
+) -> Result<(String, u64), AppError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send,
+    E: Display,
+{
+    // Stream the file bytes through encryption into storage
+    let size = AtomicU64::new(0);
+    let encrypted_file = match content_key {
+        Some(content_key) => encrypt_stream(limit_upload_size(file, &size), content_key),
+        None => limit_upload_size(file, &size),
+    };
+
+    let cid = app_state.storage.put(name, encrypted_file).await?;
+    let size = size.into_inner();
 
-    upload_file_request.cid =
-        upload_file_to_cloud(&encrypted_file, &upload_file_request.file_name).await?;
+    if let Err(errors) = validate_upload_size(size) {
+        app_state.storage.delete(&cid).await?;
+        return Err(AppError::Validation(errors));
+    }
 
     tracing::info!("File uploaded to server");
 
-    let file = FileCollection::from(upload_file_request.clone());
+    Ok((cid, size))
+}
+
+/// Saves the metadata of a stored upload.
+///
+/// # Returns
+/// - `Ok((String, String))` with the id and the share code of the new file.
+pub async fn save_file(
+    app_state: &AppState,
+    upload_file_request: UploadFileRequest,
+) -> Result<(String, String), AppError> {
+    let file = FileCollection::from(upload_file_request);
+    let share_code = file
+        .share_code
+        .clone()
+        .ok_or_else(|| AppError::Internal("File without a share code".to_string()))?;
 
     let uploaded_file_result = app_state.file_collection.insert_one(file).await?;
 
     tracing::info!("File metadata uploaded to database");
 
+    Ok((
+        object_id_to_str(&uploaded_file_result.inserted_id.as_object_id())?,
+        share_code,
+    ))
+}
+
+/// Turns the bytes of an upload into a `BlobStream` that fails once the file
+/// grows past `MAX_UPLOAD_SIZE`.
+///
+/// # Parameters
+/// - `file`: The bytes of the file, e.g. a multipart `file` field.
+/// - `size`: Counter holding the number of bytes read so far.
+fn limit_upload_size<'a, S, E>(file: S, size: &'a AtomicU64) -> BlobStream<'a>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'a,
+    E: Display,
+{
+    file.map(move |chunk| {
+        let chunk =
+            chunk.map_err(|e| AppError::Internal(format!("Error reading file bytes: {}", e)))?;
+
+        let total = size.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+        if !chunk.is_empty() {
+            validate_upload_size(total)?;
+        }
+
+        Ok(chunk)
+    })
+    .boxed()
+}
+
+/// Password of files uploaded without one before share keys existed.
+const LEGACY_DEFAULT_PASSWORD: &str = "default-password";
+
+/// Checks the credentials of a download and issues a single-use download ticket.
+///
+/// Accepts the share `code` of the file (in short or word form; `file_id` for older files)
+/// and either the `password` of the file or the `key` from its share link,
+/// or the `token` of one of the file's share links (plus its `password` if it has one).
+/// For a bundle, `member` picks one of its files; without it the bundle is a ZIP.
+/// Credentials are read from the JSON body, never from the URL, so they don't end up in
+/// access logs, proxies or browser history; the password may also be sent as the password
+/// of an `Authorization: Basic` header.
+///
+/// Verifies the file's existence and expiration and unwraps its key, which is sealed
+/// with the ticket. The ticket is bound to the client's address, can be redeemed once
+/// with `download_file` within `DOWNLOAD_TICKET_SECONDS`, and is only stored hashed.
+/// Download limits are enforced when the ticket is redeemed.
+///
+/// # Parameters
+/// - `basic_auth`: The password from an optional `Authorization: Basic` header.
+/// - `app_state`: Shared application state with DB and file access.
+/// - `payload`: JSON body with the `code` or `token` and the optional `password` or `key`.
+///
+/// # Returns
+/// - `201 Created` with the `ticket` and the `url` to download the file from.
+/// - `AppError::Unauthorized` if the password or key is missing or wrong.
+/// - `AppError::BadRequest` if the file or link does not exist, has expired or was revoked.
+///
+/// # Example
+/// ```http
+/// POST /file/download
+/// { "code": "k3Jd9_Qm2xVb0aZt7LpW1g", "password": "12345" }
+/// ```
+pub async fn create_download_ticket(
+    basic_auth: ExtractBasicAuth,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(app_state): Extension<AppState>,
+    Json(payload): Json<DownloadFileRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    // A share link brings its own limits and key slot
+    let share_link = match &payload.token {
+        Some(token) => Some(
+            find_share_link(&app_state, token)
+                .await?
+                .ok_or_else(|| AppError::BadRequest("No such share link exists!".to_string()))?,
+        ),
+        None => None,
+    };
+    let file = find_download(
+        &app_state,
+        share_link.as_ref(),
+        payload.code.or(payload.file_id),
+    )
+    .await?;
+
+    check_available(&app_state, &file, share_link.as_ref()).await?;
+
+    // A share link is opened with its password, or else its token
+    let password = basic_auth.password.or(payload.password);
+    let secret = match &share_link {
+        Some(share_link) if share_link.password_protected => password,
+        Some(_) => payload.token,
+        None => password.or(payload.key),
+    };
+
+    let file_key = unlock_download(&app_state, &file, share_link.as_ref(), secret).await?;
+
+    let ticket = generate_share_key();
+    let expires_at = Utc::now() + Duration::seconds(DOWNLOAD_TICKET_SECONDS);
+
+    let download_ticket = DownloadTicketCollection {
+        id: None,
+        ticket_hash: hash_token(&ticket),
+        file_id: file
+            .id
+            .ok_or_else(|| AppError::Internal("File without an id".to_string()))?,
+        share_link_id: share_link.and_then(|share_link| share_link.id),
+        ip_address: addr.ip().to_string(),
+        sealed_key: file_key
+            .as_ref()
+            .map(|file_key| seal_with_ticket(&ticket, file_key.as_bytes()))
+            .transpose()?,
+        is_password: matches!(file_key, Some(UnlockedKey::Password(_))),
+        member: payload.member,
+        expires_at,
+    };
+
+    app_state
+        .download_ticket_collection
+        .insert_one(download_ticket)
+        .await?;
+
     Ok((
         StatusCode::CREATED,
-        Json(UploadFileResponse {
-            message: "File uploaded successfully".to_string(),
-            id: object_id_to_str(&uploaded_file_result.inserted_id.as_object_id())?,
+        Json(DownloadTicketResponse {
+            url: format!("/file/download/{}", ticket),
+            ticket,
+            expires_at,
         }),
     ))
 }
 
-/// Handles secure file downloads based on file ID and optional password.
+/// Redeems a download ticket issued by `create_download_ticket`.
+///
+/// The ticket is consumed by the first request, so resuming an interrupted download
+/// needs a new ticket. Verifies that the file (and the share link the ticket was issued
+/// through) can still be downloaded, then decrypts and streams the file as a
+/// downloadable attachment; the limit of the file, or of its share link, applies and
+/// the download is attributed to the link.
+///
+/// Supports single `Range` requests (with `If-Range` validated against the ETag),
+/// so interrupted downloads can be resumed. A `Range` request from a client that
+/// downloaded the file within the last hour continues that download session and
+/// does not count as a new download.
+///
+/// A download slot is reserved atomically before any bytes are served, and given back
+/// if fetching or decrypting the file fails.
 ///
-/// Accepts a JSON payload with `file_id` and an optional `password`.
-/// Verifies the file's existence, expiration, and download limits before
-/// decrypting and returning the file as a downloadable attachment.
+/// Client-side encrypted files are returned as stored, without a password; their
+/// opaque metadata is sent in the `X-File-Metadata` header.
 ///
 /// # Parameters
+/// - `headers`: Request headers (`Range`, `If-Range`, `User-Agent`).
 /// - `app_state`: Shared application state with DB and file access.
-/// - `payload`: JSON body containing the file ID and optional password.
+/// - `ticket`: The download ticket.
 ///
 /// # Returns
 /// - `200 OK` with the decrypted file and appropriate headers on success.
-/// - `AppError` on invalid ID, missing file, decryption failure, or limits exceeded.
+/// - `206 Partial Content` with the requested range of the decrypted file.
+/// - `416 Range Not Satisfiable` if the range lies outside of the file.
+/// - `AppError::Unauthorized` if the ticket is unknown, used, expired or from another client.
+/// - `AppError` on missing file, decryption failure, or limits exceeded.
 ///
 /// # Security
-/// Files are encrypted at rest and decrypted using the provided or default password.
+/// Files are encrypted at rest and decrypted with the key sealed in the ticket.
 ///
 /// # Example
 /// ```http
-/// GET /file/download?file_id=6811a257200ffe8eb047b776&password=12345
+/// GET /file/download/Qm2xVb0aZt7LpW1gk3Jd9_Qm2xVb0aZt7LpW1gk3Jd9
+/// Range: bytes=1048576-
 /// ```
 pub async fn download_file(
     headers: HeaderMap,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Extension(app_state): Extension<AppState>,
-    Query(query): Query<DownloadFileRequest>,
+    Path(ticket): Path<String>,
 ) -> Result<impl IntoResponse, AppError> {
-    let file_id = str_to_object_id(&query.file_id)?;
-    let password = match query.password {
-        Some(password) => password,
-        None => String::from("default-password"),
-    };
+    // Single use: the first request of the client it was issued to takes the ticket;
+    // requests from anywhere else leave it alone
+    let ip_address = addr.ip().to_string();
+    let download_ticket = app_state
+        .download_ticket_collection
+        .find_one_and_delete(doc! {
+            "ticket_hash": hash_token(&ticket),
+            "ip_address": &ip_address,
+            "expires_at": {"$gt": bson::DateTime::from_chrono(Utc::now())},
+        })
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired download ticket".to_string()))?;
 
-    // get file
     let file = app_state
         .file_collection
-        .find_one(doc! {"_id": file_id})
+        .find_one(doc! {"_id": download_ticket.file_id})
         .await?
         .ok_or_else(|| AppError::BadRequest("No such file exists!".to_string()))?;
 
-    // check expiry date and download count
-    if file.expires_at <= Utc::now() {
-        return Err(AppError::BadRequest(
-            "File has already expired.".to_string(),
-        ));
+    let share_link = match download_ticket.share_link_id {
+        Some(share_link_id) => Some(
+            app_state
+                .share_link_collection
+                .find_one(doc! {"_id": share_link_id})
+                .await?
+                .ok_or_else(|| AppError::BadRequest("No such share link exists!".to_string()))?,
+        ),
+        None => None,
+    };
+
+    check_available(&app_state, &file, share_link.as_ref()).await?;
+
+    let key = download_ticket
+        .sealed_key
+        .map(|sealed_key| open_with_ticket(&ticket, &sealed_key))
+        .transpose()?;
+
+    let file_key = match (file.encryption_mode, &key) {
+        (EncryptionMode::Server, Some(password)) if download_ticket.is_password => {
+            Some(FileKey::Password(std::str::from_utf8(password).map_err(
+                |_| AppError::Internal("Invalid sealed password".to_string()),
+            )?))
+        }
+        (EncryptionMode::Server, Some(content_key)) => Some(FileKey::DataKey(
+            content_key
+                .as_slice()
+                .try_into()
+                .map_err(|_| AppError::Internal("Invalid content key length".to_string()))?,
+        )),
+        (EncryptionMode::Server, None) => {
+            return Err(AppError::Internal("Ticket without a file key".to_string()));
+        }
+        // the server holds no key
+        (EncryptionMode::Client, _) => None,
+    };
+
+    serve_download(
+        &app_state,
+        &headers,
+        ip_address,
+        file,
+        share_link.as_ref(),
+        file_key,
+        download_ticket.member.as_deref(),
+    )
+    .await
+}
+
+/// Downloads a file with a plain `GET`, for `curl`, `wget` and browser links.
+///
+/// `share` is either the share code of the file (in short or word form; the id of an
+/// older file) or the token of one of its share links. The rest of the path picks a file
+/// of a bundle (a bundle without one is served as a ZIP); for single files it is ignored,
+/// so clients can save the download under it. The password is read from an
+/// `Authorization: Basic` header (the user name is ignored, e.g. `curl -u :secret`);
+/// files uploaded without a password take the share key (the fragment of their share
+/// link) there instead.
+/// Without a valid password the response is a `401` with a `WWW-Authenticate: Basic`
+/// challenge, so browsers ask for it.
+///
+/// The same expiry, download limit and `Range` rules apply as for `download_file`.
+///
+/// # Parameters
+/// - `headers`: Request headers (`Range`, `If-Range`, `User-Agent`).
+/// - `basic_auth`: The password from an optional `Authorization: Basic` header.
+/// - `app_state`: Shared application state with DB and file access.
+/// - `path`: The share code or share link token, and the ignored file name.
+///
+/// # Returns
+/// - `200 OK` or `206 Partial Content` with the decrypted file.
+/// - `401 Unauthorized` with a `WWW-Authenticate` challenge if the password is missing or wrong.
+/// - `AppError` on missing file, decryption failure, or limits exceeded.
+///
+/// # Example
+/// ```http
+/// GET /d/k3Jd9_Qm2xVb0aZt7LpW1g/report.pdf
+/// Authorization: Basic OjEyMzQ1
+/// ```
+pub async fn direct_download(
+    headers: HeaderMap,
+    basic_auth: ExtractBasicAuth,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(app_state): Extension<AppState>,
+    Path(path): Path<DirectDownloadPath>,
+) -> Result<Response<Body>, AppError> {
+    let share = path.share;
+
+    // Share link tokens and share codes are both opaque to clients
+    let share_link = find_share_link(&app_state, &share).await?;
+    let (code, token) = match share_link {
+        Some(_) => (None, Some(share)),
+        None => (Some(share), None),
+    };
+
+    let file = find_download(&app_state, share_link.as_ref(), code).await?;
+
+    check_available(&app_state, &file, share_link.as_ref()).await?;
+
+    // A share link is opened with its password, or else its token
+    let secret = match &share_link {
+        Some(share_link) if share_link.password_protected => basic_auth.password,
+        Some(_) => token,
+        None => basic_auth.password,
+    };
+
+    let file_key = match unlock_download(&app_state, &file, share_link.as_ref(), secret).await {
+        Ok(file_key) => file_key,
+        Err(AppError::Unauthorized(message)) => {
+            let mut response = AppError::Unauthorized(message).into_response();
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"fileshare-rs\", charset=\"UTF-8\""),
+            );
+            return Ok(response);
+        }
+        Err(e) => return Err(e),
+    };
+
+    serve_download(
+        &app_state,
+        &headers,
+        addr.ip().to_string(),
+        file,
+        share_link.as_ref(),
+        file_key.as_ref().map(UnlockedKey::file_key),
+        path.file_name.as_deref(),
+    )
+    .await
+}
+
+/// The key a download is decrypted with, once the client's credentials were checked.
+enum UnlockedKey {
+    ContentKey([u8; 32]),
+    /// The password of a file stored before data keys existed.
+    Password(String),
+}
+
+impl UnlockedKey {
+    fn file_key(&self) -> FileKey<'_> {
+        match self {
+            UnlockedKey::ContentKey(content_key) => FileKey::DataKey(*content_key),
+            UnlockedKey::Password(password) => FileKey::Password(password),
+        }
     }
 
-    if file.download_count >= file.max_downloads {
-        return Err(AppError::BadRequest(
-            "File has reached its maximum download limit.".to_string(),
-        ));
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            UnlockedKey::ContentKey(content_key) => content_key,
+            UnlockedKey::Password(password) => password.as_bytes(),
+        }
+    }
+}
+
+/// Finds the share link with the given token.
+async fn find_share_link(
+    app_state: &AppState,
+    token: &str,
+) -> Result<Option<ShareLinkCollection>, AppError> {
+    Ok(app_state
+        .share_link_collection
+        .find_one(doc! {"token_hash": hash_token(token)})
+        .await?)
+}
+
+/// Finds the file a download asks for, through its share link or by its share code.
+///
+/// # Returns
+/// - `AppError::BadRequest` if neither is given or there is no such file.
+async fn find_download(
+    app_state: &AppState,
+    share_link: Option<&ShareLinkCollection>,
+    code: Option<String>,
+) -> Result<FileCollection, AppError> {
+    let filter = match (share_link, code) {
+        (Some(share_link), _) => doc! {"_id": share_link.file_id},
+        (None, Some(code)) => share_code_filter(&code)?,
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "A share `code` or a share link `token` is required".to_string(),
+            ))
+        }
+    };
+
+    // get file
+    app_state
+        .file_collection
+        .find_one(filter)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No such file exists!".to_string()))
+}
+
+/// Checks the password (or share key, or link token) of a download and unwraps the
+/// key of the file with it.
+///
+/// # Returns
+/// - `Ok(None)` for `Client` mode files, which are served as stored.
+/// - `AppError::Unauthorized` if `secret` is missing or wrong.
+async fn unlock_download(
+    app_state: &AppState,
+    file: &FileCollection,
+    share_link: Option<&ShareLinkCollection>,
+    secret: Option<String>,
+) -> Result<Option<UnlockedKey>, AppError> {
+    let key_slots = match share_link {
+        Some(share_link) => share_link.key_slot.iter().cloned().collect(),
+        None => file.key_slots.clone(),
+    };
+
+    let file_key = match file.encryption_mode {
+        EncryptionMode::Server if key_slots.is_empty() => {
+            // Older files without a password were encrypted with a fixed one
+            Some(UnlockedKey::Password(
+                secret.unwrap_or_else(|| LEGACY_DEFAULT_PASSWORD.to_string()),
+            ))
+        }
+        EncryptionMode::Server => {
+            let secret = secret.ok_or_else(|| {
+                AppError::Unauthorized("A password or key is required".to_string())
+            })?;
+
+            Some(UnlockedKey::ContentKey(
+                unwrap_content_key(
+                    app_state.key_provider.as_ref(),
+                    &key_slots,
+                    file.shred_key.as_ref(),
+                    &secret,
+                )
+                .await?,
+            ))
+        }
+        // the server holds no key
+        EncryptionMode::Client => None,
+    };
+
+    Ok(file_key)
+}
+
+/// Fails unless a file, and the share link it is downloaded through, can still be downloaded.
+///
+/// Records it if the file expired or ran out of downloads since its status was stored.
+/// The download limit itself is checked by `serve_download`, since the last download of
+/// an exhausted file can still be resumed.
+///
+/// # Returns
+/// - `AppError::Gone` if the file expired, was revoked or purged, each with its own message.
+/// - `AppError::BadRequest` if the share link was revoked or expired.
+pub async fn check_available(
+    app_state: &AppState,
+    file: &FileCollection,
+    share_link: Option<&ShareLinkCollection>,
+) -> Result<(), AppError> {
+    record_status(app_state, file).await?;
+
+    match file.status() {
+        FileStatus::Active | FileStatus::Exhausted => {}
+        FileStatus::Expired => {
+            return Err(AppError::Gone("File has already expired.".to_string()));
+        }
+        FileStatus::Revoked => {
+            return Err(AppError::Gone(
+                "File has been revoked by its owner.".to_string(),
+            ));
+        }
+        FileStatus::Purging | FileStatus::Purged => {
+            return Err(AppError::Gone("File has already been deleted.".to_string()));
+        }
+    }
+
+    if let Some(share_link) = share_link {
+        if share_link.revoked_at.is_some() {
+            return Err(AppError::BadRequest(
+                "Share link has been revoked.".to_string(),
+            ));
+        }
+
+        if share_link.expires_at <= Utc::now() {
+            return Err(AppError::BadRequest(
+                "Share link has already expired.".to_string(),
+            ));
+        }
     }
 
-    // fetch encrypted file content
-    let encrypted_file = read_file_from_cloud(file.cid).await?;
+    Ok(())
+}
+
+/// Streams a file to a client that was granted access to it, see `download_file`.
+///
+/// Checks the download limit of the file (or of its share link), reserves a download
+/// slot unless the request continues an earlier download from the first byte it did
+/// not send, and records the download.
+/// A bundle is served as one of its files, or else as a ZIP built on the fly (which
+/// can't be resumed); either counts as one download of the bundle.
+///
+/// # Parameters
+/// - `headers`: Request headers (`Range`, `If-Range`, `User-Agent`).
+/// - `ip_address`: The address of the client.
+/// - `file`: The file to serve, already checked with `check_available`.
+/// - `share_link`: The share link the file is downloaded through, if any.
+/// - `file_key`: What the file is decrypted with; `None` serves it as stored.
+/// - `member`: The path of the file of a bundle to serve; ignored for single files.
+pub async fn serve_download(
+    app_state: &AppState,
+    headers: &HeaderMap,
+    ip_address: String,
+    file: FileCollection,
+    share_link: Option<&ShareLinkCollection>,
+    file_key: Option<FileKey<'_>>,
+    member: Option<&str>,
+) -> Result<Response<Body>, AppError> {
+    let file_id = file
+        .id
+        .ok_or_else(|| AppError::Internal("File without an id".to_string()))?;
+    let share_link_id = share_link.and_then(|share_link| share_link.id);
+
+    // The blob to serve, or `None` for the ZIP of a bundle
+    let blob = match (file.is_bundle(), member) {
+        (true, Some(path)) => {
+            let (index, member) = file
+                .members
+                .iter()
+                .enumerate()
+                .find(|(_, member)| member.path == path)
+                .ok_or_else(|| AppError::NotFound("No such file in the bundle".to_string()))?;
 
-    // decrypt file
-    let decrypted_file = decrypt_file_with_password(&encrypted_file, &password)?;
+            Some(DownloadBlob {
+                cid: member.cid.clone(),
+                size: member.size,
+                name: member
+                    .path
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                mime_type: member.mime_type.clone(),
+                etag: format!("\"{}-{}\"", file_id.to_hex(), index),
+            })
+        }
+        (true, None) => None,
+        (false, _) => Some(DownloadBlob {
+            cid: file.cid.clone(),
+            size: file.size,
+            name: file.name.clone(),
+            mime_type: file.mime_type.clone(),
+            etag: format!("\"{}\"", file_id.to_hex()),
+        }),
+    };
 
     let user_agent = headers
         .get("user-agent")
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    let mut all_downloads = file.downloads.clone();
-    all_downloads.push(DownloadEntry::new(addr.ip().to_string(), user_agent));
+    let range = match &blob {
+        Some(blob) => match resolve_range(headers, &blob.etag, blob.size) {
+            RangeRequest::Full => None,
+            RangeRequest::Partial(range) => Some(range),
+            RangeRequest::Unsatisfiable => {
+                return Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", blob.size))
+                    .body(Body::empty())
+                    .map_err(|e| AppError::Internal(format!("Error in download file : {}", e)));
+            }
+        },
+        // A ZIP is built on the fly, so it is always sent whole
+        None => None,
+    };
+    let member = member
+        .filter(|_| file.is_bundle())
+        .map(|path| path.to_string());
+
+    // A range request picking up where the client's last download stopped is not a
+    // new download; anything starting before that (e.g. `bytes=0-`) is
+    let is_same_session = match &range {
+        Some(range) if range.start > 0 => app_state
+            .download_collection
+            .find_one(doc! {
+                "file_id": file_id,
+                "member": &member,
+                "share_link_id": share_link_id,
+                "ip_address": &ip_address,
+                "user_agent": &user_agent,
+                "downloaded_at": {"$gt": bson::DateTime::from_chrono(download_session_start())},
+            })
+            .sort(doc! {"downloaded_at": -1})
+            .await?
+            .is_some_and(|last| {
+                last.status != DownloadStatus::Started
+                    && range.start >= last.range_start + last.bytes_sent
+            }),
+        _ => false,
+    };
+
+    // check download count
+    let (download_count, max_downloads) = share_link
+        .map_or((file.download_count, file.max_downloads), |share_link| {
+            (share_link.download_count, share_link.max_downloads)
+        });
+    if !is_same_session && download_count >= max_downloads {
+        return Err(download_limit_error(share_link.is_some()));
+    }
+
+    let reservation = if is_same_session {
+        None
+    } else {
+        Some(reserve_download(app_state, file_id, share_link_id).await?)
+    };
 
-    let update_doc = doc! {
-        "$set": {
-            "download_count": (file.download_count + 1) as i32,
-            "downloads": bson::to_bson(&all_downloads)
-                .map_err(|_| AppError::Internal("Failed to serialize downloads".to_string()))?,
+    let download = DownloadCollection {
+        member,
+        range_start: range.as_ref().map_or(0, |range| range.start),
+        ..DownloadCollection::new(
+            file_id,
+            share_link_id,
+            file.user_id,
+            ip_address,
+            user_agent,
+            is_same_session,
+        )
+    };
+    let download_id = match app_state.download_collection.insert_one(download).await {
+        Ok(result) => result
+            .inserted_id
+            .as_object_id()
+            .ok_or_else(|| AppError::Internal("Failed to read the id of the download".to_string())),
+        Err(e) => Err(e.into()),
+    };
+    let download_id = match download_id {
+        Ok(download_id) => download_id,
+        Err(e) => {
+            if let Some(reservation) = &reservation {
+                release_download(app_state, reservation).await?;
+            }
+            return Err(e);
         }
     };
+    let mut tracker = DownloadTracker::new(app_state.clone(), download_id, reservation);
 
-    // increase download count
-    app_state
-        .file_collection
-        .update_one(doc! {"_id": file_id}, update_doc)
-        .await?;
+    let file_content = match &blob {
+        Some(blob) => {
+            let blob_range = range.clone().unwrap_or(0..blob.size);
+            match &file_key {
+                // fetch and decrypt file content (fails here on a wrong legacy password)
+                Some(file_key) => {
+                    decrypt_range(
+                        app_state.storage.clone(),
+                        &blob.cid,
+                        blob.size,
+                        file_key,
+                        blob_range,
+                    )
+                    .await
+                }
+                // serve the ciphertext as-is
+                None => Ok(read_range(
+                    app_state.storage.clone(),
+                    blob.cid.clone(),
+                    blob_range,
+                )),
+            }
+        }
+        None => bundle_zip(app_state, &file, file_key.as_ref()),
+    };
+
+    let file_content = match file_content {
+        Ok(file_content) => track_download(file_content, tracker),
+        Err(e) => {
+            tracker.finish(DownloadStatus::Failed, true);
+            return Err(e);
+        }
+    };
 
-    let mime_type = file.mime_type;
+    let blob = blob.unwrap_or_else(|| DownloadBlob {
+        cid: String::new(),
+        size: zip_size(
+            file.members
+                .iter()
+                .map(|member| (member.path.as_str(), member.size)),
+        ),
+        name: match file.name.ends_with(".zip") {
+            true => file.name.clone(),
+            false => format!("{}.zip", file.name),
+        },
+        mime_type: "application/zip".to_string(),
+        etag: String::new(),
+    });
 
     // Set headers
+    let header_value = |value: &str| {
+        HeaderValue::from_str(value)
+            .map_err(|e| AppError::Internal(format!("Error in download file : {}", e)))
+    };
     let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", HeaderValue::from_str(&mime_type).unwrap());
+    headers.insert("Content-Type", header_value(&blob.mime_type)?);
     headers.insert(
         "Content-Disposition",
-        HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file.name)).unwrap(),
+        header_value(&content_disposition(&blob.name))?,
     );
+    if blob.etag.is_empty() {
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+    } else {
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        headers.insert(header::ETAG, header_value(&blob.etag)?);
+    }
+    if let Some(client_metadata) = &file.client_metadata {
+        headers.insert("X-File-Metadata", header_value(client_metadata)?);
+    }
+
+    let status = match &range {
+        Some(range) => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!(
+                    "bytes {}-{}/{}",
+                    range.start,
+                    range.end - 1,
+                    blob.size
+                ))?,
+            );
+            headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(range.end - range.start),
+            );
+            StatusCode::PARTIAL_CONTENT
+        }
+        None => {
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(blob.size));
+            StatusCode::OK
+        }
+    };
 
-    let mut response_builder = Response::builder().status(StatusCode::OK);
+    let mut response_builder = Response::builder().status(status);
     if let Some(headers_mut) = response_builder.headers_mut() {
         headers_mut.extend(headers);
     }
 
     let response = response_builder
-        .body(Body::from(decrypted_file))
+        .body(Body::from_stream(file_content))
         .map_err(|e| AppError::Internal(format!("Error in download file : {}", e)))?;
 
     Ok(response)
 }
 
-/// Retrieves all uploaded files (metadata) for a user.
-///
-/// This function expects an authentication token in the request header. It validates the token,
-/// checks its expiration.
-///
-/// # Parameters
-/// - `agent`: Auth parameters containing the `user_id` (to identify the requesting user).
-/// - `app_state`: Shared application state with DB and file access
+/// The stored blob a download serves: a single file or one file of a bundle.
+struct DownloadBlob {
+    cid: String,
+    size: u64, // bytes
+    name: String,
+    mime_type: String,
+    etag: String,
+}
+
+/// Streams all files of a bundle as one ZIP, decrypting each only when it is reached.
+fn bundle_zip(
+    app_state: &AppState,
+    file: &FileCollection,
+    file_key: Option<&FileKey<'_>>,
+) -> Result<BlobStream<'static>, AppError> {
+    let content_key = match file_key {
+        Some(FileKey::DataKey(content_key)) => Some(*content_key),
+        // Bundles were never encrypted with a password directly
+        Some(FileKey::Password(_)) => {
+            return Err(AppError::Internal("Bundle without a data key".to_string()));
+        }
+        None => None,
+    };
+
+    let entries = file
+        .members
+        .iter()
+        .map(|member| {
+            let storage = app_state.storage.clone();
+            let cid = member.cid.clone();
+            let size = member.size;
+
+            let content = stream::once(async move {
+                match content_key {
+                    Some(content_key) => {
+                        decrypt_range(storage, &cid, size, &FileKey::DataKey(content_key), 0..size)
+                            .await
+                    }
+                    None => Ok(read_range(storage, cid, 0..size)),
+                }
+            })
+            .try_flatten()
+            .boxed();
+
+            ZipEntry {
+                path: member.path.clone(),
+                size,
+                content,
+            }
+        })
+        .collect();
+
+    Ok(zip_stream(entries, file.uploaded_at))
+}
+
+/// Returns what a recipient needs to know about a file before downloading it.
+///
+/// Only reads the file's metadata; storage is never touched and no download is used up.
+/// The name and MIME type are left out if the owner chose to hide them.
+///
+/// # Parameters
+/// - `app_state`: Shared application state with DB references.
+/// - `code`: The share code of the file, in short or word form (or the id of an older file).
+///
+/// # Returns
+/// - `200 OK` with the file info.
+/// - `AppError::NotFound` if the file does not exist.
+///
+/// # Example
+/// ```http
+/// GET /file/k3Jd9_Qm2xVb0aZt7LpW1g/info
+/// ```
+pub async fn file_info(
+    Extension(app_state): Extension<AppState>,
+    Path(code): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = app_state
+        .file_collection
+        .find_one(share_code_filter(&code)?)
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    let downloads_remaining = file.max_downloads.saturating_sub(file.download_count);
+    let status = file.status();
+
+    Ok((
+        StatusCode::OK,
+        Json(FileInfoResponse {
+            name: (!file.hide_metadata).then_some(file.name),
+            mime_type: (!file.hide_metadata).then_some(file.mime_type),
+            size: file.size,
+            expires_at: file.expires_at,
+            downloads_remaining,
+            password_required: file.password_protected,
+            encryption_mode: file.encryption_mode,
+            metadata: file.client_metadata,
+            members: (!file.members.is_empty() && !file.hide_metadata).then(|| {
+                file.members
+                    .into_iter()
+                    .map(|member| BundleMemberView {
+                        path: member.path,
+                        size: member.size,
+                    })
+                    .collect()
+            }),
+            status,
+            is_available: status == FileStatus::Active,
+        }),
+    ))
+}
+
+/// Changes the name, expiry, download limit or metadata visibility of a file (owner only).
+///
+/// Every field is optional; the given ones are validated like on upload. A later expiry
+/// or a higher download limit makes an expired or exhausted file `Active` again.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with any of `file_name`, `expires_at`, `max_downloads`, `hide_metadata`.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::Validation` if a field is invalid.
+/// - `AppError::BadRequest` if the file was revoked or purged.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// PATCH /file/6811a257200ffe8eb047b776
+/// { "expires_at": "2025-07-01T00:00:00Z", "max_downloads": 5 }
+/// ```
+pub async fn update_file(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<UpdateFileRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file_id = str_to_object_id(&file_id)?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    // Revoked and purged files are gone for good
+    if matches!(
+        file.status(),
+        FileStatus::Revoked | FileStatus::Purging | FileStatus::Purged
+    ) {
+        return Err(AppError::BadRequest(
+            "File can no longer be changed.".to_string(),
+        ));
+    }
+
+    // A later expiry or a higher download limit brings an expired or exhausted file back
+    let is_downloadable = payload.expires_at.unwrap_or(file.expires_at) > Utc::now()
+        && file.download_count < payload.max_downloads.unwrap_or(file.max_downloads);
+
+    let mut update_doc = doc! {};
+    if let Some(file_name) = payload.file_name {
+        update_doc.insert("name", sanitize_file_name(&file_name));
+    }
+    if let Some(expires_at) = payload.expires_at {
+        update_doc.insert(
+            "expires_at",
+            bson::to_bson(&expires_at)
+                .map_err(|_| AppError::Internal("Failed to serialize expires_at".to_string()))?,
+        );
+    }
+    if let Some(max_downloads) = payload.max_downloads {
+        update_doc.insert("max_downloads", max_downloads as i32);
+    }
+    if let Some(hide_metadata) = payload.hide_metadata {
+        update_doc.insert("hide_metadata", hide_metadata);
+    }
+
+    if update_doc.is_empty() {
+        return Err(AppError::BadRequest("Nothing to update".to_string()));
+    }
+
+    // Only while no download or sweep changed the status in between
+    let filter = doc! {
+        "_id": file_id,
+        "purge": null,
+        "status": stored_status_filter(&[file.status])?,
+    };
+
+    let is_updated = match file.status {
+        FileStatus::Expired | FileStatus::Exhausted if is_downloadable => set_file_status(
+            &app_state,
+            filter,
+            FileStatus::Active,
+            doc! {"$set": update_doc},
+        )
+        .await?
+        .is_some(),
+        _ => {
+            app_state
+                .file_collection
+                .update_one(filter, doc! {"$set": update_doc})
+                .await?
+                .matched_count
+                > 0
+        }
+    };
+
+    if !is_updated {
+        return Err(AppError::BadRequest(
+            "File was changed in the meantime, please try again.".to_string(),
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(UpdateFileResponse {
+            message: "File updated successfully".to_string(),
+        }),
+    ))
+}
+
+/// Deletes a file (owner only): stops its downloads, removes its share links and
+/// download history, then purges its keys and blobs.
+///
+/// The file is marked `Purging` before anything is removed, so if purging fails
+/// half-way the sweeper finishes it. Like revoked files, it stays listed with its
+/// status and purge record.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB and file access.
+/// - `file_id`: The id of the file.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::BadRequest` if the file was already purged.
+/// - `AppError::NotFound` if the user has no such file.
+/// - `AppError` variants for DB errors.
+///
+/// # Example
+/// ```http
+/// DELETE /file/6811a257200ffe8eb047b776
+/// ```
+pub async fn delete_file(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    // Purged before statuses were stored
+    if file.status() == FileStatus::Purged {
+        return Err(AppError::BadRequest(
+            "File has already been deleted.".to_string(),
+        ));
+    }
+
+    // Active files are revoked first, as only files that can't be downloaded are purged
+    set_file_status(
+        &app_state,
+        doc! {"_id": file_id, "purge": null},
+        FileStatus::Revoked,
+        doc! {},
+    )
+    .await?;
+    let file = set_file_status(
+        &app_state,
+        doc! {"_id": file_id, "purge": null},
+        FileStatus::Purging,
+        doc! {},
+    )
+    .await?
+    .ok_or_else(|| AppError::BadRequest("File has already been deleted.".to_string()))?;
+
+    app_state
+        .share_link_collection
+        .delete_many(doc! {"file_id": file_id})
+        .await?;
+
+    app_state
+        .download_collection
+        .delete_many(doc! {"file_id": file_id})
+        .await?;
+
+    // The sweeper retries it
+    if let Err(e) = purge_file(&app_state, &file).await {
+        tracing::error!("Error in purging file {:?}: {:?}", file.id, e);
+    }
+
+    tracing::info!("File deleted");
+
+    Ok((
+        StatusCode::OK,
+        Json(DeleteFileResponse {
+            message: "File deleted successfully".to_string(),
+        }),
+    ))
+}
+
+/// A download slot taken by `reserve_download`.
+struct DownloadReservation {
+    file_id: ObjectId,
+    share_link_id: Option<ObjectId>,
+}
+
+/// Takes one of the download slots of a file, or of its share link, in a single atomic update.
+///
+/// The update only matches while the file is active (the link unrevoked), unexpired and
+/// has downloads left, so concurrent downloads can never exceed `max_downloads`. Taking
+/// the last slot of a file marks it `Exhausted`. A slot of a share link is given back
+/// right away unless its file, checked after the update, is still downloadable.
+///
+/// # Returns
+/// * `Ok(DownloadReservation)` to release the slot again if serving the file fails.
+/// * `Err(AppError::Gone)` if no slot of the file is left, or the file of the share
+///   link is no longer available.
+/// * `Err(AppError::BadRequest)` if no slot of the share link is left.
+async fn reserve_download(
+    app_state: &AppState,
+    file_id: ObjectId,
+    share_link_id: Option<ObjectId>,
+) -> Result<DownloadReservation, AppError> {
+    let now = date_to_bson(&Utc::now())?;
+    let has_downloads_left = doc! {"$lt": ["$download_count", "$max_downloads"]};
+    let increment = doc! {"$inc": {"download_count": 1}};
+
+    let is_reserved = match share_link_id {
+        Some(share_link_id) => {
+            let is_reserved = app_state
+                .share_link_collection
+                .find_one_and_update(
+                    doc! {
+                        "_id": share_link_id,
+                        "revoked_at": null,
+                        "expires_at": {"$gt": now.clone()},
+                        "$expr": has_downloads_left,
+                    },
+                    increment,
+                )
+                .await?
+                .is_some();
+
+            // The file may have expired, been revoked or purged since it was checked;
+            // a slot taken after that is given back
+            let is_file_available = !is_reserved
+                || app_state
+                    .file_collection
+                    .find_one(doc! {
+                        "_id": file_id,
+                        "status": stored_status_filter(&[FileStatus::Active, FileStatus::Exhausted])?,
+                        "purge": null,
+                        "expires_at": {"$gt": now},
+                    })
+                    .await?
+                    .is_some();
+            if !is_file_available {
+                let reservation = DownloadReservation {
+                    file_id,
+                    share_link_id: Some(share_link_id),
+                };
+                release_download(app_state, &reservation).await?;
+                return Err(AppError::Gone("File is no longer available.".to_string()));
+            }
+
+            is_reserved
+        }
+        None => {
+            let file = app_state
+                .file_collection
+                .find_one_and_update(
+                    doc! {
+                        "_id": file_id,
+                        "status": stored_status_filter(&[FileStatus::Active])?,
+                        "purge": null,
+                        "expires_at": {"$gt": now},
+                        "$expr": has_downloads_left,
+                    },
+                    increment,
+                )
+                .return_document(ReturnDocument::After)
+                .await?;
+
+            if let Some(file) = &file {
+                record_status(app_state, file).await?;
+            }
+
+            file.is_some()
+        }
+    };
+
+    if !is_reserved {
+        return Err(download_limit_error(share_link_id.is_some()));
+    }
+
+    Ok(DownloadReservation {
+        file_id,
+        share_link_id,
+    })
+}
+
+/// The error for a download over the limit of a file, or of its share link.
+fn download_limit_error(is_share_link: bool) -> AppError {
+    match is_share_link {
+        true => {
+            AppError::BadRequest("Share link has reached its maximum download limit.".to_string())
+        }
+        false => AppError::Gone("File has reached its maximum download limit.".to_string()),
+    }
+}
+
+/// Gives a download slot back.
+async fn release_download(
+    app_state: &AppState,
+    reservation: &DownloadReservation,
+) -> Result<(), AppError> {
+    let decrement = doc! {"$inc": {"download_count": -1}};
+
+    match reservation.share_link_id {
+        Some(share_link_id) => {
+            app_state
+                .share_link_collection
+                .update_one(
+                    doc! {"_id": share_link_id, "download_count": {"$gt": 0}},
+                    decrement,
+                )
+                .await?
+        }
+        None => {
+            let update_result = app_state
+                .file_collection
+                .update_one(
+                    doc! {"_id": reservation.file_id, "download_count": {"$gt": 0}},
+                    decrement,
+                )
+                .await?;
+
+            // The slot was the last one
+            set_file_status(
+                app_state,
+                doc! {
+                    "_id": reservation.file_id,
+                    "status": stored_status_filter(&[FileStatus::Exhausted])?,
+                    "expires_at": {"$gt": date_to_bson(&Utc::now())?},
+                    "$expr": {"$lt": ["$download_count", "$max_downloads"]},
+                },
+                FileStatus::Active,
+                doc! {},
+            )
+            .await?;
+
+            update_result
+        }
+    };
+
+    tracing::info!("Download slot released");
+
+    Ok(())
+}
+
+/// Records how a download went in its `DownloadCollection` entry.
+///
+/// A download dropped before it finished (e.g. the client went away) is recorded as failed,
+/// but keeps its slot: the client may already have most of the file.
+struct DownloadTracker {
+    app_state: AppState,
+    download_id: ObjectId,
+    reservation: Option<DownloadReservation>,
+    started_at: Instant,
+    bytes_sent: u64,
+    is_finished: bool,
+}
+
+impl DownloadTracker {
+    fn new(
+        app_state: AppState,
+        download_id: ObjectId,
+        reservation: Option<DownloadReservation>,
+    ) -> Self {
+        DownloadTracker {
+            app_state,
+            download_id,
+            reservation,
+            started_at: Instant::now(),
+            bytes_sent: 0,
+            is_finished: false,
+        }
+    }
+
+    /// Saves the outcome of the download, once, in the background.
+    ///
+    /// # Arguments
+    /// * `status` - How the download ended.
+    /// * `release` - Whether to give the download slot back.
+    fn finish(&mut self, status: DownloadStatus, release: bool) {
+        if self.is_finished {
+            return;
+        }
+        self.is_finished = true;
+
+        // Nothing can be saved while the runtime shuts down
+        let Ok(runtime) = Handle::try_current() else {
+            return;
+        };
+
+        let app_state = self.app_state.clone();
+        let download_id = self.download_id;
+        let bytes_sent = self.bytes_sent;
+        let duration_ms = self.started_at.elapsed().as_millis() as u64;
+        let reservation = if release {
+            self.reservation.take()
+        } else {
+            None
+        };
+
+        runtime.spawn(async move {
+            if let Err(e) = app_state
+                .download_collection
+                .update_one(
+                    doc! {"_id": download_id},
+                    doc! {"$set": {
+                        "status": bson::to_bson(&status).unwrap_or_default(),
+                        "bytes_sent": bytes_sent as i64,
+                        "duration_ms": duration_ms as i64,
+                    }},
+                )
+                .await
+            {
+                tracing::error!("Error in recording download: {:?}", e);
+            }
+
+            if let Some(reservation) = reservation {
+                if let Err(e) = release_download(&app_state, &reservation).await {
+                    tracing::error!("Error in releasing download slot: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+impl Drop for DownloadTracker {
+    fn drop(&mut self) {
+        self.finish(DownloadStatus::Failed, false);
+    }
+}
+
+/// Counts the bytes of a download as they are sent and records the outcome.
+///
+/// The download slot is released if the file fails while it is being streamed.
+fn track_download(
+    file_content: BlobStream<'static>,
+    tracker: DownloadTracker,
+) -> BlobStream<'static> {
+    stream::unfold(
+        (file_content, tracker),
+        |(mut file_content, mut tracker)| async move {
+            match file_content.next().await {
+                Some(Ok(chunk)) => {
+                    tracker.bytes_sent += chunk.len() as u64;
+                    Some((Ok(chunk), (file_content, tracker)))
+                }
+                Some(Err(e)) => {
+                    tracker.finish(DownloadStatus::Failed, true);
+                    Some((Err(e), (file_content, tracker)))
+                }
+                None => {
+                    tracker.finish(DownloadStatus::Completed, false);
+                    None
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Adds a password to a file (owner only).
+///
+/// The file's data key is unwrapped with `current_password` and wrapped again with
+/// `new_password`; the stored blob is not touched.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with `current_password` and `new_password`.
+///
+/// # Returns
+/// - `201 Created` with a success message.
+/// - `AppError::Unauthorized` if `current_password` is wrong.
+/// - `AppError::BadRequest` if the file already has `MAX_KEY_SLOTS` passwords or has no data key.
+///
+/// # Example
+/// ```http
+/// POST /file/6811a257200ffe8eb047b776/passwords
+/// { "current_password": "12345", "new_password": "for-bob" }
+/// ```
+pub async fn add_file_password(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<FilePasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file = find_keyed_file(&app_state, &file_id, agent.user_id).await?;
+
+    if file.key_slots.len() >= MAX_KEY_SLOTS {
+        return Err(AppError::BadRequest(format!(
+            "A file can have at most {} passwords",
+            MAX_KEY_SLOTS
+        )));
+    }
+
+    let (_, data_key) = unwrap_data_key(&file.key_slots, &payload.current_password)?;
+
+    let mut key_slots = file.key_slots.clone();
+    key_slots.push(wrap_data_key(&data_key, &payload.new_password)?);
+    update_key_slots(&app_state, &file, key_slots).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(FilePasswordResponse {
+            message: "Password added successfully".to_string(),
+        }),
+    ))
+}
+
+/// Changes one password of a file (owner only).
+///
+/// Replaces the key slot `current_password` opens with one for `new_password`;
+/// other passwords of the file keep working.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with `current_password` and `new_password`.
 ///
 /// # Returns
-/// - `200 OK` with a success message and the list of user files.
-/// - `AppError::BadRequest` if query parameters are missing or invalid, if the token has expired, or if the user is invalid.
+/// - `200 OK` with a success message.
+/// - `AppError::Unauthorized` if `current_password` is wrong.
+pub async fn change_file_password(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<FilePasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file = find_keyed_file(&app_state, &file_id, agent.user_id).await?;
+
+    let (index, data_key) = unwrap_data_key(&file.key_slots, &payload.current_password)?;
+
+    let mut key_slots = file.key_slots.clone();
+    key_slots[index] = wrap_data_key(&data_key, &payload.new_password)?;
+    update_key_slots(&app_state, &file, key_slots).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(FilePasswordResponse {
+            message: "Password changed successfully".to_string(),
+        }),
+    ))
+}
+
+/// Removes a password from a file (owner only).
+///
+/// The last password of a file can not be removed, as nobody could open it afterwards.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with the `password` to remove.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::Unauthorized` if `password` is not a password of the file.
+/// - `AppError::BadRequest` if it is the only password of the file.
+pub async fn remove_file_password(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<RemoveFilePasswordRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = find_keyed_file(&app_state, &file_id, agent.user_id).await?;
+
+    let (index, _) = unwrap_data_key(&file.key_slots, &payload.password)?;
+
+    if file.key_slots.len() == 1 {
+        return Err(AppError::BadRequest(
+            "Can not remove the only password of a file".to_string(),
+        ));
+    }
+
+    let mut key_slots = file.key_slots.clone();
+    key_slots.remove(index);
+    update_key_slots(&app_state, &file, key_slots).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(FilePasswordResponse {
+            message: "Password removed successfully".to_string(),
+        }),
+    ))
+}
+
+/// Fetches a file of a user whose passwords can be managed, i.e. one with a data key.
+async fn find_keyed_file(
+    app_state: &AppState,
+    file_id: &str,
+    user_id: ObjectId,
+) -> Result<FileCollection, AppError> {
+    let file_id = str_to_object_id(file_id)?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    if file.key_slots.is_empty() {
+        return Err(AppError::BadRequest(
+            "The passwords of this file can not be changed".to_string(),
+        ));
+    }
+
+    Ok(file)
+}
+
+/// Replaces the key slots of a file, unless they were changed since it was read.
+async fn update_key_slots(
+    app_state: &AppState,
+    file: &FileCollection,
+    key_slots: Vec<KeySlot>,
+) -> Result<(), AppError> {
+    let to_bson = |key_slots: &[KeySlot]| {
+        bson::to_bson(key_slots)
+            .map_err(|_| AppError::Internal("Failed to serialize key slots".to_string()))
+    };
+
+    let update_result = app_state
+        .file_collection
+        .update_one(
+            doc! {"_id": file.id, "key_slots": to_bson(&file.key_slots)?},
+            doc! {
+                "$set": {
+                    "key_slots": to_bson(&key_slots)?,
+                    "password_protected": is_password_protected(&key_slots),
+                }
+            },
+        )
+        .await?;
+
+    if update_result.matched_count == 0 {
+        return Err(AppError::BadRequest(
+            "The passwords of this file were changed in the meantime".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Retrieves a page of the files (metadata) of a user.
+///
+/// Files can be filtered by status, MIME type, name and upload date and sorted by
+/// upload date, expiry, name or size. Pages are fetched with the opaque `next_cursor`
+/// of the previous page, so they stay consistent while files are added.
+///
+/// # Parameters
+/// - `agent`: Auth parameters containing the `user_id` (to identify the requesting user).
+/// - `app_state`: Shared application state with DB and file access
+/// - `query`: Pagination, filter and sort parameters.
+///
+/// # Returns
+/// - `200 OK` with the page of `FileView`s and the cursor of the next page.
+/// - `AppError::BadRequest` or `AppError::Validation` if query parameters are invalid.
 /// - `AppError::Internal` or `AppError::Database` if there are server or database-related issues.
 ///
 /// # Example
 /// ```http
-/// GET /file/user-files
+/// GET /file/user-files?status=active&mime_type=image/&sort_by=size&order=desc&limit=20
 /// ```
 pub async fn user_files(
     agent: ExtractAuthAgent,
     Extension(app_state): Extension<AppState>,
+    Query(query): Query<UserFilesRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = query.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let sort_field = query.sort_by.unwrap_or_default().field();
+    let order = query.order.unwrap_or_default();
+
+    let mut filters = vec![doc! {"user_id": agent.user_id}];
+
+    if let Some(status) = query.status {
+        filters.push(status_filter(status)?);
+    }
+    if let Some(mime_type) = &query.mime_type {
+        filters.push(match mime_type.strip_suffix('/') {
+            Some(prefix) => doc! {"mime_type": {"$regex": format!("^{}/", escape_regex(prefix))}},
+            None => doc! {"mime_type": mime_type},
+        });
+    }
+    if let Some(name) = &query.name {
+        filters.push(doc! {"name": {"$regex": escape_regex(name), "$options": "i"}});
+    }
+    if let Some(uploaded_after) = &query.uploaded_after {
+        filters.push(doc! {"uploaded_at": {"$gte": date_to_bson(uploaded_after)?}});
+    }
+    if let Some(uploaded_before) = &query.uploaded_before {
+        filters.push(doc! {"uploaded_at": {"$lt": date_to_bson(uploaded_before)?}});
+    }
+    if let Some(cursor) = &query.cursor {
+        filters.push(Cursor::decode(cursor)?.filter(sort_field, order));
+    }
+
+    // One extra file tells whether there is a next page
     let mut files = app_state
         .file_collection
-        .find(doc! {"user_id": agent.user_id})
+        .find(doc! {"$and": filters})
+        .sort(sort_by(sort_field, order))
+        .limit(limit as i64 + 1)
         .await?;
 
-    let mut response = Vec::<FileCollection>::new();
+    let mut page = Vec::<FileCollection>::new();
 
     while let Some(file) = files
         .try_next()
         .await
         .map_err(|e| AppError::Internal(format!("Error in fetching user files{}", e)))?
     {
-        response.push(file);
+        page.push(file);
+    }
+
+    let next_cursor = if page.len() > limit as usize {
+        page.truncate(limit as usize);
+
+        let last = page
+            .last()
+            .map(bson::to_document)
+            .transpose()
+            .map_err(|_| AppError::Internal("Failed to serialize file".to_string()))?
+            .ok_or_else(|| AppError::Internal("Empty page".to_string()))?;
+
+        Some(Cursor::after(&last, sort_field)?.encode()?)
+    } else {
+        None
+    };
+
+    let files = page
+        .into_iter()
+        .map(FileView::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(UserFilesResponse { files, next_cursor }),
+    ))
+}
+
+/// Retrieves a page of the download history of a file (owner only), newest first.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `query`: Pagination parameters and an optional `status` filter.
+///
+/// # Returns
+/// - `200 OK` with the page of `DownloadView`s and the cursor of the next page.
+/// - `AppError::BadRequest` or `AppError::Validation` if query parameters are invalid.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// GET /file/6811a257200ffe8eb047b776/downloads?status=completed&limit=20
+/// ```
+pub async fn file_downloads(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Query(query): Query<FileDownloadsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = query.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file_id = str_to_object_id(&file_id)?;
+
+    app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let sort_field = "downloaded_at";
+    let order = SortOrder::Desc;
+
+    let mut filters = vec![doc! {"file_id": file_id}];
+
+    if let Some(status) = query.status {
+        filters.push(doc! {
+            "status": bson::to_bson(&status)
+                .map_err(|_| AppError::Internal("Failed to serialize status".to_string()))?,
+        });
+    }
+    if let Some(cursor) = &query.cursor {
+        filters.push(Cursor::decode(cursor)?.filter(sort_field, order));
+    }
+
+    // One extra download tells whether there is a next page
+    let mut downloads = app_state
+        .download_collection
+        .find(doc! {"$and": filters})
+        .sort(sort_by(sort_field, order))
+        .limit(limit as i64 + 1)
+        .await?;
+
+    let mut page = Vec::<DownloadCollection>::new();
+
+    while let Some(download) = downloads
+        .try_next()
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in fetching downloads{}", e)))?
+    {
+        page.push(download);
     }
 
-    Ok((StatusCode::OK, Json(UserFilesResponse { files: response })))
+    let next_cursor = if page.len() > limit as usize {
+        page.truncate(limit as usize);
+
+        let last = page
+            .last()
+            .map(bson::to_document)
+            .transpose()
+            .map_err(|_| AppError::Internal("Failed to serialize download".to_string()))?
+            .ok_or_else(|| AppError::Internal("Empty page".to_string()))?;
+
+        Some(Cursor::after(&last, sort_field)?.encode()?)
+    } else {
+        None
+    };
+
+    let downloads = page
+        .into_iter()
+        .map(DownloadView::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        StatusCode::OK,
+        Json(FileDownloadsResponse {
+            downloads,
+            next_cursor,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::file::is_password_protected,
+        utils::{envelope::unwrap_data_key, share_code::generate_share_code},
+    };
+
+    #[test]
+    fn share_links_carry_the_share_key_in_their_fragment() {
+        let share_code = generate_share_code();
+        let data_key = generate_data_key();
+
+        let (key_slot, share_key) = wrap_new_data_key(&data_key, None).unwrap();
+        let link = share_link(&share_code, share_key.as_deref());
+        let key_slots = [key_slot];
+        assert!(!is_password_protected(&key_slots));
+
+        // The recipient finds the file by its share code and opens it with the key
+        let (path, share_key) = link.split_once('#').unwrap();
+        assert_eq!(path, format!("/d/{}", share_code));
+        assert_eq!(
+            unwrap_data_key(&key_slots, share_key).unwrap(),
+            (0, data_key)
+        );
+
+        // Files with a password get no key in their link
+        let (key_slot, share_key) = wrap_new_data_key(&data_key, Some("12345")).unwrap();
+        assert!(is_password_protected(&[key_slot]));
+        assert_eq!(
+            share_link(&share_code, share_key.as_deref()),
+            format!("/d/{}", share_code)
+        );
+    }
 }
@@ -0,0 +1,390 @@
+use axum::{extract::Path, response::IntoResponse, Extension, Json};
+use chrono::Utc;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    options::ReturnDocument,
+};
+use reqwest::StatusCode;
+
+use crate::{
+    config::AppState,
+    dtos::file::RevokeFileResponse,
+    error::AppError,
+    models::file::{FileCollection, FileStatus, PurgeRecord, StatusChange},
+    utils::{
+        extractor::ExtractAuthAgent,
+        misc::{date_to_bson, str_to_object_id},
+    },
+};
+
+/// Stops every download of a file (owner only), through its share code, share links,
+/// signed URLs and download tickets alike.
+///
+/// The file is kept until the sweeper purges it, so its history and purge record stay
+/// visible to the owner.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::BadRequest` if the file was already revoked or purged.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// POST /file/6811a257200ffe8eb047b776/revoke
+/// ```
+pub async fn revoke_file(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    // Purged before statuses were stored
+    if file.status() == FileStatus::Purged {
+        return Err(AppError::BadRequest(
+            "File has already been deleted.".to_string(),
+        ));
+    }
+
+    set_file_status(
+        &app_state,
+        doc! {"_id": file_id, "purge": null},
+        FileStatus::Revoked,
+        doc! {},
+    )
+    .await?
+    .ok_or_else(|| AppError::BadRequest("File can no longer be revoked.".to_string()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RevokeFileResponse {
+            message: "File revoked successfully".to_string(),
+        }),
+    ))
+}
+
+/// Moves the file matching `filter` to `status`, recording when it did.
+///
+/// Unless `filter` narrows the status itself, the file must be in one of the
+/// `predecessors` of `status`, so concurrent requests and sweeps can't undo each other.
+///
+/// # Arguments
+/// * `filter` - Which file to move.
+/// * `status` - Where to move it.
+/// * `update` - More changes to make in the same update, e.g. `{"$unset": ...}`.
+///
+/// # Returns
+/// * `Ok(Some(FileCollection))` with the file after the update.
+/// * `Ok(None)` if no file matched.
+pub async fn set_file_status(
+    app_state: &AppState,
+    mut filter: Document,
+    status: FileStatus,
+    update: Document,
+) -> Result<Option<FileCollection>, AppError> {
+    if !filter.contains_key("status") {
+        filter.insert("status", stored_status_filter(status.predecessors())?);
+    }
+
+    Ok(app_state
+        .file_collection
+        .find_one_and_update(filter, status_change(status, update)?)
+        .return_document(ReturnDocument::After)
+        .await?)
+}
+
+/// Moves every file matching `filter` to `status`, like `set_file_status`.
+///
+/// # Returns
+/// * `Ok(u64)` with the number of files moved.
+pub async fn set_files_status(
+    app_state: &AppState,
+    mut filter: Document,
+    status: FileStatus,
+) -> Result<u64, AppError> {
+    if !filter.contains_key("status") {
+        filter.insert("status", stored_status_filter(status.predecessors())?);
+    }
+
+    Ok(app_state
+        .file_collection
+        .update_many(filter, status_change(status, doc! {})?)
+        .await?
+        .modified_count)
+}
+
+/// Adds setting the status of a file, and recording when it changed, to `update`.
+fn status_change(status: FileStatus, mut update: Document) -> Result<Document, AppError> {
+    let now = Utc::now();
+
+    let mut set = update.get_document("$set").cloned().unwrap_or_default();
+    set.insert("status", status_to_bson(status)?);
+    set.insert("status_changed_at", date_to_bson(&now)?);
+    update.insert("$set", set);
+
+    update.insert(
+        "$push",
+        doc! {
+            "status_history": bson::to_bson(&StatusChange {
+                status,
+                changed_at: now,
+            })
+            .map_err(|_| AppError::Internal("Failed to serialize status".to_string()))?,
+        },
+    );
+
+    Ok(update)
+}
+
+/// Records what a download found out about a file: that it expired or ran out of
+/// downloads since its status was last stored.
+pub async fn record_status(app_state: &AppState, file: &FileCollection) -> Result<(), AppError> {
+    let status = file.status();
+
+    if status != file.status && matches!(status, FileStatus::Expired | FileStatus::Exhausted) {
+        set_file_status(
+            app_state,
+            doc! {"_id": file.id, "purge": null},
+            status,
+            doc! {},
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Destroys the shred key and deletes the blobs of a file being purged, then removes
+/// its keys and marks it `Purged`.
+pub async fn purge_file(app_state: &AppState, file: &FileCollection) -> Result<(), AppError> {
+    let key_shredded = match &file.shred_key {
+        Some(shred_key) => match app_state.key_provider.destroy(&shred_key.key_id).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!("Error in destroying key of file {:?}: {:?}", file.id, e);
+                false
+            }
+        },
+        None => false,
+    };
+
+    // A destroyed shred key leaves the blob unreadable, so a failed delete is only recorded
+    let mut blob_deleted = true;
+    for cid in file.blobs() {
+        if let Err(e) = delete_blob(app_state, cid).await {
+            tracing::error!("Error in deleting blob of file {:?}: {:?}", file.id, e);
+            blob_deleted = false;
+        }
+    }
+
+    let purge = PurgeRecord {
+        purged_at: Utc::now(),
+        blob_deleted,
+        key_shredded,
+    };
+
+    set_file_status(
+        app_state,
+        doc! {"_id": file.id},
+        FileStatus::Purged,
+        doc! {
+            "$set": {
+                "purge": bson::to_bson(&purge)
+                    .map_err(|_| AppError::Internal("Failed to serialize purge".to_string()))?,
+            },
+            "$unset": {"key_slots": "", "shred_key": ""},
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a blob from storage unless it is already gone.
+async fn delete_blob(app_state: &AppState, cid: &str) -> Result<(), AppError> {
+    if app_state.storage.exists(cid).await? {
+        app_state.storage.delete(cid).await?;
+    }
+
+    Ok(())
+}
+
+/// Filter matching files whose stored status is one of `statuses`.
+pub fn stored_status_filter(statuses: &[FileStatus]) -> Result<Bson, AppError> {
+    let mut values = statuses
+        .iter()
+        .map(|status| status_to_bson(*status))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Files stored before statuses were have none
+    if statuses.contains(&FileStatus::Active) {
+        values.push(Bson::Null);
+    }
+
+    Ok(Bson::Document(doc! {"$in": values}))
+}
+
+/// Filter matching the files in a status, see [`FileCollection::status`].
+///
+/// Also matches the files that expired or ran out of downloads since their status was
+/// last stored.
+pub fn status_filter(status: FileStatus) -> Result<Document, AppError> {
+    let now = date_to_bson(&Utc::now())?;
+    let is_active = stored_status_filter(&[FileStatus::Active])?;
+    let is_exhausted = doc! {"$expr": {"$gte": ["$download_count", "$max_downloads"]}};
+
+    Ok(match status {
+        FileStatus::Active => doc! {
+            "status": is_active,
+            "purge": null,
+            "expires_at": {"$gt": now},
+            "$nor": [is_exhausted],
+        },
+        FileStatus::Expired => doc! {
+            "$or": [
+                {"status": status_to_bson(status)?},
+                {
+                    "status": stored_status_filter(&[FileStatus::Active, FileStatus::Exhausted])?,
+                    "purge": null,
+                    "expires_at": {"$lte": now},
+                },
+            ],
+        },
+        FileStatus::Exhausted => doc! {
+            "expires_at": {"$gt": now},
+            "$or": [
+                {"status": status_to_bson(status)?},
+                {
+                    "status": is_active,
+                    "purge": null,
+                    "$expr": {"$gte": ["$download_count", "$max_downloads"]},
+                },
+            ],
+        },
+        FileStatus::Purged => doc! {
+            "$or": [
+                {"status": status_to_bson(status)?},
+                {"status": is_active, "purge": {"$ne": null}},
+            ],
+        },
+        FileStatus::Revoked | FileStatus::Purging => doc! {"status": status_to_bson(status)?},
+    })
+}
+
+fn status_to_bson(status: FileStatus) -> Result<Bson, AppError> {
+    bson::to_bson(&status).map_err(|_| AppError::Internal("Failed to serialize status".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUSES: [FileStatus; 6] = [
+        FileStatus::Active,
+        FileStatus::Expired,
+        FileStatus::Exhausted,
+        FileStatus::Revoked,
+        FileStatus::Purging,
+        FileStatus::Purged,
+    ];
+
+    /// Whether `set_file_status` matches a file stored in `from` when moving it to `to`.
+    fn can_move(from: FileStatus, to: FileStatus) -> bool {
+        let filter = stored_status_filter(to.predecessors()).unwrap();
+        let stored = status_to_bson(from).unwrap();
+
+        filter
+            .as_document()
+            .and_then(|filter| filter.get_array("$in").ok())
+            .unwrap()
+            .contains(&stored)
+    }
+
+    #[test]
+    fn allows_only_the_lifecycle_transitions() {
+        use FileStatus::*;
+
+        let allowed = [
+            (Active, Expired),
+            (Active, Exhausted),
+            (Active, Revoked),
+            (Expired, Active),
+            (Expired, Revoked),
+            (Expired, Purging),
+            (Exhausted, Active),
+            (Exhausted, Expired),
+            (Exhausted, Revoked),
+            (Exhausted, Purging),
+            (Revoked, Purging),
+            (Purging, Purging),
+            (Purging, Purged),
+        ];
+
+        for from in STATUSES {
+            for to in STATUSES {
+                assert_eq!(
+                    can_move(from, to),
+                    allowed.contains(&(from, to)),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn treats_files_without_a_status_as_active() {
+        let filter = stored_status_filter(FileStatus::Revoked.predecessors()).unwrap();
+        assert!(filter
+            .as_document()
+            .unwrap()
+            .get_array("$in")
+            .unwrap()
+            .contains(&Bson::Null));
+
+        let filter = stored_status_filter(FileStatus::Purged.predecessors()).unwrap();
+        assert!(!filter
+            .as_document()
+            .unwrap()
+            .get_array("$in")
+            .unwrap()
+            .contains(&Bson::Null));
+    }
+
+    #[test]
+    fn records_when_the_status_changed() {
+        let update = status_change(
+            FileStatus::Revoked,
+            doc! {"$set": {"name": "a.txt"}, "$unset": {"shred_key": ""}},
+        )
+        .unwrap();
+
+        let set = update.get_document("$set").unwrap();
+        assert_eq!(set.get_str("status").unwrap(), "revoked");
+        assert_eq!(set.get_str("name").unwrap(), "a.txt");
+        // Like every date of a file, see `date_to_bson`
+        let changed_at = set.get_str("status_changed_at").unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(changed_at).is_ok());
+        assert!(update.get_document("$unset").is_ok());
+
+        let change = update
+            .get_document("$push")
+            .unwrap()
+            .get_document("status_history")
+            .unwrap();
+        assert_eq!(change.get_str("status").unwrap(), "revoked");
+        assert_eq!(change.get_str("changed_at").unwrap(), changed_at);
+    }
+}
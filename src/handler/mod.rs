@@ -1,2 +1,8 @@
+pub mod admin;
 pub mod file;
+pub mod lifecycle;
+pub mod share_link;
+pub mod signed_url;
+pub mod stats;
+pub mod upload;
 pub mod user;
@@ -0,0 +1,239 @@
+use axum::{extract::Path, response::IntoResponse, Extension, Json};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId};
+use reqwest::StatusCode;
+use validator::Validate;
+
+use crate::{
+    config::AppState,
+    dtos::file::{
+        CreateShareLinkRequest, CreateShareLinkResponse, RevokeShareLinkResponse, ShareLinkView,
+        ShareLinksResponse,
+    },
+    error::AppError,
+    handler::file::check_available,
+    models::{
+        file::{EncryptionMode, FileCollection},
+        share_link::ShareLinkCollection,
+    },
+    utils::{
+        envelope::{generate_share_key, unwrap_data_key, wrap_data_key},
+        extractor::ExtractAuthAgent,
+        hashing::hash_token,
+        misc::{date_to_bson, object_id_to_str, str_to_object_id},
+    },
+};
+
+/// Builds the download link of a share link.
+pub fn share_link_url(token: &str) -> String {
+    format!("/d/{}", token)
+}
+
+/// Creates a new share link for a file (owner only).
+///
+/// The link gets its own random token, expiry and download limit, and optionally a
+/// password and a label. For server-encrypted files the data key is unwrapped with
+/// `current_password` and wrapped again for the link, so the link keeps working when
+/// the file's passwords change.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with `max_downloads` and optional `label`, `expires_at`,
+///   `password` and `current_password`.
+///
+/// # Returns
+/// - `201 Created` with the link's `id`, `token` and `link`; the token is only returned here.
+/// - `AppError::Unauthorized` if `current_password` is missing or wrong.
+/// - `AppError::BadRequest` if the link would outlive the file.
+/// - `AppError::Gone` if the file expired, was revoked or purged.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// POST /file/6811a257200ffe8eb047b776/links
+/// { "label": "for legal", "max_downloads": 3, "current_password": "12345" }
+/// ```
+pub async fn create_share_link(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<CreateShareLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file = find_user_file(&app_state, &file_id, agent.user_id).await?;
+
+    check_available(&app_state, &file, None).await?;
+
+    let expires_at = payload.expires_at.unwrap_or(file.expires_at);
+    if expires_at > file.expires_at {
+        return Err(AppError::BadRequest(
+            "A share link can not expire after its file".to_string(),
+        ));
+    }
+
+    let token = generate_share_key();
+
+    let key_slot = match file.encryption_mode {
+        // Older files without a data key can only be shared by their id
+        EncryptionMode::Server if file.key_slots.is_empty() => {
+            return Err(AppError::BadRequest(
+                "Share links can not be created for this file".to_string(),
+            ));
+        }
+        EncryptionMode::Server => {
+            let current_password = payload.current_password.as_deref().ok_or_else(|| {
+                AppError::Unauthorized("The password of the file is required".to_string())
+            })?;
+            let (_, data_key) = unwrap_data_key(&file.key_slots, current_password)?;
+
+            let mut key_slot =
+                wrap_data_key(&data_key, payload.password.as_deref().unwrap_or(&token))?;
+            key_slot.is_share_key = payload.password.is_none();
+            Some(key_slot)
+        }
+        // the server holds no key
+        EncryptionMode::Client => None,
+    };
+
+    let share_link = ShareLinkCollection {
+        id: None,
+        file_id: file
+            .id
+            .ok_or_else(|| AppError::Internal("File without an id".to_string()))?,
+        user_id: agent.user_id,
+        label: payload.label,
+        token_hash: hash_token(&token),
+        expires_at,
+        max_downloads: payload.max_downloads,
+        download_count: 0,
+        password_protected: payload.password.is_some(),
+        key_slot,
+        created_at: Utc::now(),
+        revoked_at: None,
+    };
+
+    let insert_result = app_state
+        .share_link_collection
+        .insert_one(share_link)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateShareLinkResponse {
+            id: object_id_to_str(&insert_result.inserted_id.as_object_id())?,
+            message: "Share link created successfully".to_string(),
+            link: share_link_url(&token),
+            token,
+        }),
+    ))
+}
+
+/// Lists the share links of a file (owner only), newest first.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+///
+/// # Returns
+/// - `200 OK` with the `ShareLinkView`s of the file, revoked ones included.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// GET /file/6811a257200ffe8eb047b776/links
+/// ```
+pub async fn share_links(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file = find_user_file(&app_state, &file_id, agent.user_id).await?;
+
+    let links = app_state
+        .share_link_collection
+        .find(doc! {"file_id": file.id})
+        .sort(doc! {"created_at": -1})
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|e| AppError::Internal(format!("Error in fetching share links{}", e)))?
+        .into_iter()
+        .map(ShareLinkView::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((StatusCode::OK, Json(ShareLinksResponse { links })))
+}
+
+/// Revokes one share link of a file (owner only); its other links keep working.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`, `link_id`: The ids of the file and of the link.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::BadRequest` if the link was already revoked.
+/// - `AppError::NotFound` if the user has no such link.
+///
+/// # Example
+/// ```http
+/// DELETE /file/6811a257200ffe8eb047b776/links/6811a2b1200ffe8eb047b777
+/// ```
+pub async fn revoke_share_link(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path((file_id, link_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+    let link_id = str_to_object_id(&link_id)?;
+
+    let share_link = app_state
+        .share_link_collection
+        .find_one(doc! {"_id": link_id, "file_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such share link exists!".to_string()))?;
+
+    if share_link.revoked_at.is_some() {
+        return Err(AppError::BadRequest(
+            "Share link has already been revoked.".to_string(),
+        ));
+    }
+
+    app_state
+        .share_link_collection
+        .update_one(
+            doc! {"_id": link_id, "revoked_at": null},
+            doc! {"$set": {"revoked_at": date_to_bson(&Utc::now())?}},
+        )
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RevokeShareLinkResponse {
+            message: "Share link revoked successfully".to_string(),
+        }),
+    ))
+}
+
+/// Fetches a file of a user.
+async fn find_user_file(
+    app_state: &AppState,
+    file_id: &str,
+    user_id: ObjectId,
+) -> Result<FileCollection, AppError> {
+    let file_id = str_to_object_id(file_id)?;
+
+    app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))
+}
@@ -0,0 +1,272 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Path, Query},
+    http::HeaderMap,
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use mongodb::bson::doc;
+use reqwest::StatusCode;
+use validator::Validate;
+
+use crate::{
+    config::AppState,
+    dtos::file::{
+        CreateSignedUrlRequest, CreateSignedUrlResponse, RevokeSignedUrlsResponse,
+        SignedDownloadRequest,
+    },
+    error::AppError,
+    handler::file::{check_available, serve_download},
+    models::file::EncryptionMode,
+    utils::{
+        envelope::{open_content_key, unwrap_data_key},
+        extractor::ExtractAuthAgent,
+        file::FileKey,
+        misc::str_to_object_id,
+        signed_url::{
+            sign_download_url, unwrap_url_key, verify_download_url, wrap_url_key, SignedUrlClaims,
+        },
+    },
+};
+
+/// How long a signed URL is valid unless asked otherwise.
+const DEFAULT_SIGNED_URL_SECONDS: i64 = 60 * 60;
+
+/// Mints a signed, time-limited download URL for a file (owner only).
+///
+/// The URL carries the file id, its expiry, optionally the only IP address allowed to
+/// use it, and (for server-encrypted files) the file's data key wrapped by the server,
+/// all signed with a key derived from the JWT secret. Recipients need neither a password
+/// nor a session. Downloads count against the file's own limit.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+/// - `payload`: JSON body with optional `expires_in`, `ip` and `current_password`.
+///
+/// # Returns
+/// - `201 Created` with the `url` and its `expires_at`.
+/// - `AppError::Unauthorized` if `current_password` is missing or wrong.
+/// - `AppError::NotFound` if the user has no such file.
+/// - `AppError::Gone` if the file expired, was revoked or purged.
+///
+/// # Example
+/// ```http
+/// POST /file/6811a257200ffe8eb047b776/signed-urls
+/// { "expires_in": 600, "ip": "203.0.113.7", "current_password": "12345" }
+/// ```
+pub async fn create_signed_url(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<CreateSignedUrlRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file_id = str_to_object_id(&file_id)?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    check_available(&app_state, &file, None).await?;
+
+    let expires_in = payload.expires_in.unwrap_or(DEFAULT_SIGNED_URL_SECONDS);
+    let expires_at = (Utc::now() + Duration::seconds(expires_in)).min(file.expires_at);
+
+    let key = match file.encryption_mode {
+        // Older files without a data key can only be downloaded with their password
+        EncryptionMode::Server if file.key_slots.is_empty() => {
+            return Err(AppError::BadRequest(
+                "Signed URLs can not be created for this file".to_string(),
+            ));
+        }
+        EncryptionMode::Server => {
+            let current_password = payload.current_password.as_deref().ok_or_else(|| {
+                AppError::Unauthorized("The password of the file is required".to_string())
+            })?;
+            let (_, data_key) = unwrap_data_key(&file.key_slots, current_password)?;
+
+            Some(wrap_url_key(
+                &app_state.signed_url_keys,
+                file_id,
+                &data_key,
+            )?)
+        }
+        // the server holds no key
+        EncryptionMode::Client => None,
+    };
+
+    let claims = SignedUrlClaims {
+        file_id,
+        expires: expires_at.timestamp(),
+        ip: payload.ip,
+        version: file.signed_url_version,
+        key,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateSignedUrlResponse {
+            url: signed_url(&app_state, &claims),
+            expires_at,
+        }),
+    ))
+}
+
+/// Invalidates every signed URL minted for a file so far (owner only).
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+///
+/// # Returns
+/// - `200 OK` with a success message.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// DELETE /file/6811a257200ffe8eb047b776/signed-urls
+/// ```
+pub async fn revoke_signed_urls(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+
+    let update_result = app_state
+        .file_collection
+        .update_one(
+            doc! {"_id": file_id, "user_id": agent.user_id},
+            doc! {"$inc": {"signed_url_version": 1}},
+        )
+        .await?;
+
+    if update_result.matched_count == 0 {
+        return Err(AppError::NotFound("No such file exists!".to_string()));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(RevokeSignedUrlsResponse {
+            message: "Signed URLs revoked successfully".to_string(),
+        }),
+    ))
+}
+
+/// Downloads a file through a signed URL minted by `create_signed_url`.
+///
+/// The signature, expiry, bound IP address and version of the URL are checked before
+/// anything else; then the file is served like by `download_file`, `Range` requests included.
+///
+/// # Parameters
+/// - `headers`: Request headers (`Range`, `If-Range`, `User-Agent`).
+/// - `app_state`: Shared application state with DB and file access.
+/// - `file_id`: The id of the file.
+/// - `query`: The signed `expires`, `ip`, `v` and `key`, and the signature `sig`.
+///
+/// # Returns
+/// - `200 OK` or `206 Partial Content` with the decrypted file.
+/// - `AppError::Unauthorized` if the URL is forged, expired, revoked or used from another IP.
+///
+/// # Example
+/// ```http
+/// GET /file/signed/6811a257200ffe8eb047b776?expires=1751328000&v=0&key=...&sig=...
+/// ```
+pub async fn signed_download(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+    Query(query): Query<SignedDownloadRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+
+    let claims = SignedUrlClaims {
+        file_id,
+        expires: query.expires,
+        ip: query.ip,
+        version: query.v,
+        key: query.key,
+    };
+    verify_download_url(
+        &app_state.signed_url_keys,
+        &claims,
+        &query.sig,
+        addr.ip(),
+        Utc::now(),
+    )?;
+
+    let file = app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id})
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No such file exists!".to_string()))?;
+
+    if file.signed_url_version != claims.version {
+        return Err(AppError::Unauthorized("URL has been revoked".to_string()));
+    }
+
+    check_available(&app_state, &file, None).await?;
+
+    let file_key = match (file.encryption_mode, &claims.key) {
+        (EncryptionMode::Server, Some(key)) => {
+            let data_key = unwrap_url_key(&app_state.signed_url_keys, file_id, key)?;
+            Some(FileKey::DataKey(
+                open_content_key(
+                    app_state.key_provider.as_ref(),
+                    &data_key,
+                    file.shred_key.as_ref(),
+                )
+                .await?,
+            ))
+        }
+        (EncryptionMode::Server, None) => {
+            return Err(AppError::Unauthorized("URL has no file key".to_string()));
+        }
+        // the server holds no key
+        (EncryptionMode::Client, _) => None,
+    };
+
+    serve_download(
+        &app_state,
+        &headers,
+        addr.ip().to_string(),
+        file,
+        None,
+        file_key,
+        None,
+    )
+    .await
+}
+
+/// Builds the signed download URL of the claims.
+fn signed_url(app_state: &AppState, claims: &SignedUrlClaims) -> String {
+    let mut url = format!(
+        "/file/signed/{}?expires={}&v={}",
+        claims.file_id.to_hex(),
+        claims.expires,
+        claims.version
+    );
+    if let Some(ip) = claims.ip {
+        url.push_str(&format!("&ip={}", ip));
+    }
+    if let Some(key) = &claims.key {
+        url.push_str(&format!("&key={}", key));
+    }
+    url.push_str(&format!(
+        "&sig={}",
+        sign_download_url(&app_state.signed_url_keys, claims)
+    ));
+
+    url
+}
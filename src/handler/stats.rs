@@ -0,0 +1,208 @@
+use axum::{extract::Path, response::IntoResponse, Extension, Json};
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, Document};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    config::AppState,
+    dtos::file::{DailyDownloads, DownloadStatsResponse, UserAgentDownloads},
+    error::AppError,
+    models::download::DownloadStatus,
+    utils::{extractor::ExtractAuthAgent, misc::str_to_object_id},
+};
+
+/// `User-Agent` patterns of each family, checked in order (bots often claim to be browsers).
+const USER_AGENT_PATTERNS: [(&str, &str); 3] = [
+    ("bot", "bot|crawl|spider|slurp|preview|facebookexternalhit"),
+    (
+        "cli",
+        "^(curl|wget|httpie|aria2|python-requests|go-http-client)",
+    ),
+    ("browser", "mozilla|opera"),
+];
+
+/// Shows the download summary of one file (owner only).
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_id`: The id of the file.
+///
+/// # Returns
+/// - `200 OK` with the `DownloadStatsResponse` of the file.
+/// - `AppError::NotFound` if the user has no such file.
+///
+/// # Example
+/// ```http
+/// GET /file/6811a257200ffe8eb047b776/stats
+/// ```
+pub async fn file_stats(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let file_id = str_to_object_id(&file_id)?;
+
+    app_state
+        .file_collection
+        .find_one(doc! {"_id": file_id, "user_id": agent.user_id})
+        .await?
+        .ok_or_else(|| AppError::NotFound("No such file exists!".to_string()))?;
+
+    let stats = download_stats(&app_state, doc! {"file_id": file_id}).await?;
+
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+/// Shows the download summary of all files of the user.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+///
+/// # Returns
+/// - `200 OK` with the `DownloadStatsResponse` of the account.
+///
+/// # Example
+/// ```http
+/// GET /file/stats
+/// ```
+pub async fn user_stats(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let stats = download_stats(&app_state, doc! {"user_id": agent.user_id}).await?;
+
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+#[derive(Deserialize)]
+struct Count {
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct StatusCount {
+    #[serde(rename = "_id")]
+    status: DownloadStatus,
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct FirstDownload {
+    avg_ms: Option<f64>,
+}
+
+/// Result of the `$facet` stage of `download_stats`.
+#[derive(Deserialize)]
+struct StatsFacets {
+    per_day: Vec<DailyDownloads>,
+    unique_ips: Vec<Count>,
+    user_agents: Vec<UserAgentDownloads>,
+    statuses: Vec<StatusCount>,
+    first_download: Vec<FirstDownload>,
+}
+
+/// Summarises the downloads matching `filter` in a single aggregation.
+///
+/// # Arguments
+/// * `app_state` - Shared application state with DB references.
+/// * `filter` - Selects the downloads, e.g. of one file or of one user.
+async fn download_stats(
+    app_state: &AppState,
+    filter: Document,
+) -> Result<DownloadStatsResponse, AppError> {
+    let user_agent_branches = USER_AGENT_PATTERNS
+        .iter()
+        .map(|(family, pattern)| {
+            doc! {
+                "case": {"$regexMatch": {
+                    "input": {"$ifNull": ["$user_agent", ""]},
+                    "regex": pattern,
+                    "options": "i",
+                }},
+                "then": family,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let pipeline = vec![
+        doc! {"$match": filter},
+        doc! {"$facet": {
+            "per_day": [
+                {"$match": {"is_resumed": false}},
+                {"$group": {
+                    "_id": {"$dateToString": {"format": "%Y-%m-%d", "date": "$downloaded_at"}},
+                    "downloads": {"$sum": 1},
+                }},
+                {"$sort": {"_id": 1}},
+                {"$project": {"_id": 0, "date": "$_id", "downloads": 1}},
+            ],
+            "unique_ips": [
+                {"$group": {"_id": "$ip_address"}},
+                {"$count": "count"},
+            ],
+            "user_agents": [
+                {"$match": {"is_resumed": false}},
+                {"$group": {
+                    "_id": {"$switch": {"branches": user_agent_branches, "default": "other"}},
+                    "downloads": {"$sum": 1},
+                }},
+                {"$sort": {"downloads": -1}},
+                {"$project": {"_id": 0, "family": "$_id", "downloads": 1}},
+            ],
+            "statuses": [
+                {"$group": {"_id": "$status", "count": {"$sum": 1}}},
+            ],
+            // `uploaded_at` is stored as a string, `$toDate` reads it either way
+            "first_download": [
+                {"$group": {"_id": "$file_id", "first": {"$min": "$downloaded_at"}}},
+                {"$lookup": {
+                    "from": app_state.file_collection.name(),
+                    "localField": "_id",
+                    "foreignField": "_id",
+                    "as": "file",
+                }},
+                {"$unwind": "$file"},
+                {"$group": {
+                    "_id": null,
+                    "avg_ms": {"$avg": {"$subtract": ["$first", {"$toDate": "$file.uploaded_at"}]}},
+                }},
+            ],
+        }},
+    ];
+
+    let facets = app_state
+        .download_collection
+        .aggregate(pipeline)
+        .await?
+        .try_next()
+        .await?
+        .ok_or_else(|| AppError::Internal("Empty download stats".to_string()))?;
+
+    let facets: StatsFacets = bson::from_document(facets)
+        .map_err(|e| AppError::Internal(format!("Error in reading download stats: {}", e)))?;
+
+    let status_count = |status: DownloadStatus| {
+        facets
+            .statuses
+            .iter()
+            .find(|count| count.status == status)
+            .map_or(0, |count| count.count)
+    };
+
+    Ok(DownloadStatsResponse {
+        downloads: facets.per_day.iter().map(|day| day.downloads).sum(),
+        unique_ips: facets.unique_ips.first().map_or(0, |ips| ips.count),
+        completed: status_count(DownloadStatus::Completed),
+        failed: status_count(DownloadStatus::Failed),
+        time_to_first_download_secs: facets
+            .first_download
+            .first()
+            .and_then(|first| first.avg_ms)
+            .map(|avg_ms| (avg_ms.max(0.0) / 1000.0) as u64),
+        per_day: facets.per_day,
+        user_agents: facets.user_agents,
+    })
+}
@@ -0,0 +1,735 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Path,
+    http::{header, HeaderMap, HeaderValue, Response},
+    response::IntoResponse,
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::{future, stream, StreamExt, TryStreamExt};
+use mongodb::bson::{doc, oid::ObjectId};
+use reqwest::StatusCode;
+use validator::Validate;
+
+use crate::{
+    config::AppState,
+    dtos::file::{validate_upload_size, UploadFileRequest, MAX_EXPIRY_DAYS, MAX_UPLOAD_SIZE},
+    error::AppError,
+    handler::file::{prepare_upload, save_file, share_link, store_blob},
+    models::{file::FileCollection, upload::UploadSessionCollection},
+    storage::{read_stream, BlobStream},
+    utils::{
+        envelope::{generate_data_key, generate_shred_key, open_content_key, wrap_new_data_key},
+        extractor::ExtractAuthAgent,
+        file::{encrypt_part, FileHeader, CHUNK_SIZE},
+        misc::{object_id_to_str, str_to_object_id},
+        tus::{http_date, parse_metadata, TUS_VERSION},
+    },
+};
+
+/// How long a file uploaded with `put_file` is kept unless asked otherwise.
+const DEFAULT_PUT_EXPIRES_IN: i64 = 24 * 60 * 60; // 1 day
+
+/// Downloads of a file uploaded with `put_file` unless asked otherwise.
+const DEFAULT_PUT_MAX_DOWNLOADS: u8 = 1;
+
+/// tus extensions supported by the upload endpoints.
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+
+/// Describes the tus server (`OPTIONS /file/uploads`).
+///
+/// # Returns
+/// - `204 No Content` with `Tus-Version`, `Tus-Extension` and `Tus-Max-Size`.
+pub async fn upload_options() -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            ("tus-version", TUS_VERSION.to_string()),
+            ("tus-extension", TUS_EXTENSIONS.to_string()),
+            ("tus-max-size", MAX_UPLOAD_SIZE.to_string()),
+        ],
+    )
+}
+
+/// Creates a resumable upload (tus `creation` extension).
+///
+/// Expects `Upload-Length` and an `Upload-Metadata` header with base64 encoded values:
+/// - `filename` (required): A user-defined name for the file.
+/// - `filetype`: The MIME type of the file.
+/// - `password`: Protects the file; without one a random share key is generated.
+/// - `expires_at`: ISO datetime for file expiration.
+/// - `max_downloads`: Max number of allowed downloads.
+/// - `hide_metadata`: `true` hides the name and MIME type from the info endpoint.
+///
+/// The same validation rules as `upload_file` apply, so invalid uploads are rejected
+/// before any byte is sent.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `headers`: Request headers (`Upload-Length`, `Upload-Metadata`).
+///
+/// # Returns
+/// - `201 Created` with the `Location` of the upload and `Upload-Expires`, plus the
+///   share key in `X-File-Key` if no password was given (it is not returned again).
+/// - `AppError` variants for missing headers, validation, or DB errors.
+///
+/// # Example
+/// ```http
+/// POST /file/uploads
+/// Tus-Resumable: 1.0.0
+/// Upload-Length: 5242880
+/// Upload-Metadata: filename cmVwb3J0LnBkZg==,filetype YXBwbGljYXRpb24vcGRm,password MTIzNDU=
+/// ```
+pub async fn create_upload(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, AppError> {
+    if headers.contains_key("upload-defer-length") {
+        return Err(AppError::BadRequest(
+            "`Upload-Defer-Length` is not supported".to_string(),
+        ));
+    }
+
+    let upload_length = header_u64(&headers, "upload-length")?;
+
+    let metadata =
+        match headers.get("upload-metadata") {
+            Some(value) => parse_metadata(value.to_str().map_err(|_| {
+                AppError::BadRequest("Invalid `Upload-Metadata` header".to_string())
+            })?)?,
+            None => Default::default(),
+        };
+
+    let mut upload_file_request = UploadFileRequest {
+        user_id: agent.user_id,
+        mime_type: "application/octet-stream".to_string(),
+        ..Default::default()
+    };
+
+    for (key, value) in metadata {
+        match key.as_str() {
+            "filename" => upload_file_request.file_name = value,
+            "filetype" => upload_file_request.mime_type = value,
+            "password" => upload_file_request.password = Some(value).filter(|v| !v.is_empty()),
+            "expires_at" => {
+                upload_file_request.expires_at = value
+                    .parse::<DateTime<chrono::Utc>>()
+                    .map_err(|e| AppError::Internal(format!("Error parsing datetime: {}", e)))?;
+            }
+            "hide_metadata" => {
+                upload_file_request.hide_metadata = value.parse::<bool>().map_err(|e| {
+                    AppError::Internal(format!("Error parsing hide_metadata: {}", e))
+                })?;
+            }
+            "max_downloads" => {
+                upload_file_request.max_downloads = value.parse::<u8>().map_err(|e| {
+                    AppError::Internal(format!("Error parsing max_downloads: {}", e))
+                })?;
+            }
+            _ => {}
+        }
+    }
+
+    if let Err(errors) = upload_file_request.validate() {
+        return Err(AppError::Validation(errors));
+    }
+    validate_upload_size(upload_length)?;
+
+    // Wrap the data key now, so the password does not have to be kept or resent
+    let file_header = FileHeader::generate();
+    let data_key = generate_data_key();
+    let (_, wrapped_shred_key) = generate_shred_key(app_state.key_provider.as_ref()).await?;
+    let (key_slot, share_key) =
+        wrap_new_data_key(&data_key, upload_file_request.password.as_deref())?;
+    let wrapped_data_key = app_state
+        .key_provider
+        .wrap(
+            &UploadSessionCollection::data_key_id(&wrapped_shred_key),
+            &data_key,
+        )
+        .await?;
+
+    let session = UploadSessionCollection::new(
+        upload_file_request,
+        upload_length,
+        hex::encode(file_header.to_bytes()),
+        wrapped_data_key,
+        key_slot,
+        wrapped_shred_key,
+    );
+    let session_expires_at = session.session_expires_at;
+
+    let result = app_state
+        .upload_session_collection
+        .insert_one(session)
+        .await?;
+    let upload_id = object_id_to_str(&result.inserted_id.as_object_id())?;
+
+    tracing::info!("Upload session created");
+
+    let mut response_headers = vec![
+        ("location", format!("/file/uploads/{}", upload_id)),
+        ("upload-expires", http_date(&session_expires_at)),
+    ];
+    if let Some(share_key) = share_key {
+        response_headers.push(("x-file-key", share_key));
+    }
+
+    empty_response(StatusCode::CREATED, response_headers)
+}
+
+/// Uploads a file sent as the raw request body, for command-line clients
+/// (e.g. `curl -T build.tar.gz https://host/file/put/build.tar.gz`).
+///
+/// The file is named after the last path segment; its type is read from `Content-Type`.
+/// Everything else comes from optional headers:
+/// - `Max-Downloads`: Max number of allowed downloads (`DEFAULT_PUT_MAX_DOWNLOADS`).
+/// - `Expires-In`: Seconds until the file expires (`DEFAULT_PUT_EXPIRES_IN`).
+/// - `X-File-Password`: Protects the file; without one a random share key is generated.
+///
+/// The same validation, encryption and storage as for `upload_file` apply.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `file_name`: The name of the file.
+/// - `headers`: Request headers (`Content-Type`, `Max-Downloads`, `Expires-In`, `X-File-Password`).
+/// - `body`: The file.
+///
+/// # Returns
+/// - `201 Created` with the share URL of the file as `text/plain` (with the share key of
+///   a file uploaded without a password as its fragment), and its id in `X-File-Id`.
+/// - `AppError` variants for invalid headers, validation, encryption, or DB errors.
+///
+/// # Example
+/// ```http
+/// PUT /file/put/build.tar.gz
+/// Authorization: Bearer <token>
+/// Max-Downloads: 3
+/// Expires-In: 86400
+/// X-File-Password: 12345
+/// ```
+pub async fn put_file(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(file_name): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, AppError> {
+    let text_header = |name: &str| -> Result<Option<&str>, AppError> {
+        headers
+            .get(name)
+            .map(|v| v.to_str().map(str::trim))
+            .transpose()
+            .map_err(|_| AppError::BadRequest(format!("Invalid `{}` header", name)))
+    };
+
+    let max_downloads = match text_header("max-downloads")? {
+        Some(value) => value
+            .parse::<u8>()
+            .map_err(|_| AppError::BadRequest("Invalid `Max-Downloads` header".to_string()))?,
+        None => DEFAULT_PUT_MAX_DOWNLOADS,
+    };
+    let expires_in = match text_header("expires-in")? {
+        Some(value) => value
+            .parse::<i64>()
+            .ok()
+            .and_then(Duration::try_seconds)
+            .filter(|expires_in| *expires_in <= Duration::days(MAX_EXPIRY_DAYS))
+            .ok_or_else(|| AppError::BadRequest("Invalid `Expires-In` header".to_string()))?,
+        None => Duration::seconds(DEFAULT_PUT_EXPIRES_IN),
+    };
+    let expires_at = Utc::now()
+        .checked_add_signed(expires_in)
+        .ok_or_else(|| AppError::BadRequest("Invalid `Expires-In` header".to_string()))?;
+
+    let mut upload_file_request = UploadFileRequest {
+        user_id: agent.user_id,
+        file_name,
+        mime_type: text_header(header::CONTENT_TYPE.as_str())?
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+        password: text_header("x-file-password")?
+            .filter(|password| !password.is_empty())
+            .map(str::to_string),
+        expires_at,
+        max_downloads,
+        ..Default::default()
+    };
+
+    let (content_key, share_key) = prepare_upload(&app_state, &mut upload_file_request).await?;
+    let (cid, size) = store_blob(
+        &app_state,
+        &upload_file_request.file_name,
+        content_key.as_ref(),
+        body.into_data_stream(),
+    )
+    .await?;
+    upload_file_request.cid = cid;
+    upload_file_request.size = size;
+
+    let (id, share_code) = save_file(&app_state, upload_file_request).await?;
+
+    // Never built from `Host`, which the client controls
+    let url = format!(
+        "{}{}",
+        app_state.public_url,
+        share_link(&share_code, share_key.as_deref())
+    );
+
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header("x-file-id", id)
+        .body(Body::from(format!("{}\n", url)))
+        .map_err(|e| AppError::Internal(format!("Error in upload : {}", e)))
+}
+
+/// Reports how many bytes of an upload the server has (tus `HEAD`).
+///
+/// The offset is always a multiple of the encryption chunk size until the upload is
+/// complete, so clients may have to resend a few bytes they already sent.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `upload_id`: The id from the `Location` of the upload.
+///
+/// # Returns
+/// - `200 OK` with `Upload-Offset`, `Upload-Length` and `Upload-Expires`.
+/// - `AppError::NotFound` if the upload does not exist, has expired, or belongs to another user.
+pub async fn upload_offset(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(upload_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let session = find_session(&app_state, &upload_id, agent.user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        [
+            ("upload-offset", session.upload_offset.to_string()),
+            ("upload-length", session.upload_length.to_string()),
+            ("upload-expires", http_date(&session.session_expires_at)),
+            ("cache-control", "no-store".to_string()),
+        ],
+    ))
+}
+
+/// Appends bytes to an upload (tus `PATCH`).
+///
+/// The bytes are encrypted as they stream in and stored as a separate part. Once
+/// the last byte has arrived, the parts are joined into one blob and the upload is
+/// turned into a regular file, whose id and share code are returned in the `X-File-Id`
+/// and `X-File-Code` headers. If that fails, an empty `PATCH` at the final offset
+/// tries again.
+///
+/// A request that breaks off keeps the whole chunks that arrived before it did, and
+/// the offset moves past them, so the client resumes from there.
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `upload_id`: The id from the `Location` of the upload.
+/// - `headers`: Request headers (`Content-Type`, `Upload-Offset`).
+/// - `body`: The bytes to append.
+///
+/// # Returns
+/// - `204 No Content` with the new `Upload-Offset` (and `X-File-Id`, `X-File-Code` once complete).
+/// - `409 Conflict` if `Upload-Offset` does not match the offset of the upload.
+/// - `AppError::BadRequest` if the body is shorter than `CHUNK_SIZE` but doesn't complete
+///   the upload.
+/// - `415 Unsupported Media Type` for any `Content-Type` but `application/offset+octet-stream`.
+/// - `AppError` variants for unknown uploads, encryption, storage, or DB errors.
+///
+/// # Example
+/// ```http
+/// PATCH /file/uploads/6811a257200ffe8eb047b776
+/// Tus-Resumable: 1.0.0
+/// Upload-Offset: 0
+/// Content-Type: application/offset+octet-stream
+/// ```
+pub async fn append_upload(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(upload_id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+
+    if content_type != Some("application/offset+octet-stream") {
+        return empty_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, []);
+    }
+
+    let offset = header_u64(&headers, "upload-offset")?;
+    let session = find_session(&app_state, &upload_id, agent.user_id).await?;
+
+    if offset != session.upload_offset {
+        return empty_response(StatusCode::CONFLICT, []);
+    }
+
+    // Every byte arrived before, but the file could not be saved
+    if offset == session.upload_length && !session.parts.is_empty() {
+        let upload_expires = http_date(&session.session_expires_at);
+        let parts = session.parts.clone();
+
+        let (file_id, share_code) = finalize_upload(&app_state, session, parts).await?;
+        return empty_response(
+            StatusCode::NO_CONTENT,
+            [
+                ("upload-offset", offset.to_string()),
+                ("upload-expires", upload_expires),
+                ("x-file-id", file_id),
+                ("x-file-code", share_code),
+            ],
+        );
+    }
+
+    let file_header = session_header(&session)?;
+    let data_key = app_state
+        .key_provider
+        .unwrap(
+            &UploadSessionCollection::data_key_id(&session.shred_key),
+            &session.wrapped_data_key,
+        )
+        .await?;
+    let content_key = open_content_key(
+        app_state.key_provider.as_ref(),
+        &data_key,
+        Some(&session.shred_key),
+    )
+    .await?;
+    let cipher = file_header.cipher_with_key(&content_key);
+
+    // A body that breaks off just ends early, so the chunks before it are still stored
+    let read_error = OnceLock::new();
+    let received = AtomicU64::new(0);
+    let plaintext: BlobStream = body
+        .into_data_stream()
+        .map_err(|e| AppError::BadRequest(format!("Error reading upload bytes: {}", e)))
+        .inspect_ok(|chunk| {
+            received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        })
+        .scan((), |_, chunk| {
+            future::ready(match chunk {
+                Ok(chunk) => Some(Ok(chunk)),
+                Err(e) => {
+                    let _ = read_error.set(e);
+                    None
+                }
+            })
+        })
+        .boxed();
+
+    let sealed = AtomicU64::new(0);
+    let mut part =
+        encrypt_part(plaintext, &cipher, offset, session.upload_length, &sealed).peekable();
+
+    // Less than a chunk arrived, which could never move the offset forward
+    if Pin::new(&mut part).peek().await.is_none() {
+        drop(part);
+        if let Some(e) = read_error.into_inner() {
+            return Err(e);
+        }
+        if received.load(Ordering::Relaxed) > 0 {
+            return Err(AppError::BadRequest(format!(
+                "Upload parts must hold at least {} bytes, unless they complete the upload",
+                CHUNK_SIZE
+            )));
+        }
+
+        return empty_response(
+            StatusCode::NO_CONTENT,
+            [("upload-offset", offset.to_string())],
+        );
+    }
+
+    let cid = app_state
+        .storage
+        .put(&session.file_name, part.boxed())
+        .await?;
+    let upload_offset = offset + sealed.into_inner();
+
+    // Only one request may move the offset forward
+    let update_result = app_state
+        .upload_session_collection
+        .update_one(
+            doc! {"_id": session.id, "upload_offset": offset as i64},
+            doc! {
+                "$set": {"upload_offset": upload_offset as i64},
+                "$push": {"parts": &cid},
+            },
+        )
+        .await?;
+
+    if update_result.matched_count == 0 {
+        app_state.storage.delete(&cid).await?;
+        return empty_response(StatusCode::CONFLICT, []);
+    }
+
+    tracing::info!("Upload part stored");
+
+    if upload_offset < session.upload_length {
+        if let Some(e) = read_error.into_inner() {
+            return Err(e);
+        }
+    }
+
+    let mut response_headers = vec![
+        ("upload-offset", upload_offset.to_string()),
+        ("upload-expires", http_date(&session.session_expires_at)),
+    ];
+
+    if upload_offset == session.upload_length {
+        let mut parts = session.parts.clone();
+        parts.push(cid);
+
+        let (file_id, share_code) = finalize_upload(&app_state, session, parts).await?;
+        response_headers.push(("x-file-id", file_id));
+        response_headers.push(("x-file-code", share_code));
+    }
+
+    empty_response(StatusCode::NO_CONTENT, response_headers)
+}
+
+/// Cancels an upload and removes what was uploaded so far (tus `termination` extension).
+///
+/// # Parameters
+/// - `agent`: Authenticated user context.
+/// - `app_state`: Shared application state with DB references.
+/// - `upload_id`: The id from the `Location` of the upload.
+///
+/// # Returns
+/// - `204 No Content` on success.
+/// - `AppError::NotFound` if the upload does not exist, has expired, or belongs to another user.
+pub async fn terminate_upload(
+    agent: ExtractAuthAgent,
+    Extension(app_state): Extension<AppState>,
+    Path(upload_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let session = find_session(&app_state, &upload_id, agent.user_id).await?;
+
+    app_state
+        .upload_session_collection
+        .delete_one(doc! {"_id": session.id})
+        .await?;
+    delete_parts(&app_state, &session.parts).await?;
+    destroy_session_keys(&app_state, &session, false).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Joins the parts of a complete upload into one blob and saves its `FileCollection`.
+///
+/// # Returns
+/// * `Ok((String, String))` with the id and the share code of the new file.
+/// * `Err(AppError)` if the upload no longer validates (e.g. `expires_at` has passed
+///   in the meantime) or storage fails; the upload is kept, so completing it can be
+///   retried until it expires.
+async fn finalize_upload(
+    app_state: &AppState,
+    session: UploadSessionCollection,
+    parts: Vec<String>,
+) -> Result<(String, String), AppError> {
+    let upload_file_request = UploadFileRequest {
+        user_id: session.user_id,
+        file_name: session.file_name.clone(),
+        size: session.upload_length,
+        mime_type: session.mime_type.clone(),
+        password: None,
+        expires_at: session.expires_at,
+        max_downloads: session.max_downloads,
+        hide_metadata: session.hide_metadata,
+        key_slots: vec![session.key_slot.clone()],
+        shred_key: Some(session.shred_key.clone()),
+        ..Default::default()
+    };
+
+    let (file_id, share_code) =
+        store_upload(app_state, &session, &parts, upload_file_request).await?;
+
+    // The file is saved; whatever is left behind expires with the session and is
+    // removed by the cleanup job
+    if let Err(e) = app_state
+        .upload_session_collection
+        .delete_one(doc! {"_id": session.id})
+        .await
+    {
+        tracing::error!("Error deleting upload session: {:?}", e);
+    }
+    if let Err(e) = delete_parts(app_state, &parts).await {
+        tracing::error!("Error deleting upload parts: {:?}", e);
+    }
+    if let Err(e) = destroy_session_keys(app_state, &session, true).await {
+        tracing::error!("Error destroying upload key: {:?}", e);
+    }
+
+    Ok((file_id, share_code))
+}
+
+/// Writes the header and the parts of an upload as one blob and saves its metadata.
+///
+/// The file gets the id of the upload, so completing an upload twice can't save two
+/// files. The blob is removed again if its metadata can't be saved.
+async fn store_upload(
+    app_state: &AppState,
+    session: &UploadSessionCollection,
+    parts: &[String],
+    mut upload_file_request: UploadFileRequest,
+) -> Result<(String, String), AppError> {
+    if let Err(errors) = upload_file_request.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let file_header = session_header(session)?;
+    let storage = app_state.storage.clone();
+
+    let blob: BlobStream = stream::once(future::ready(Ok(Bytes::from(file_header.to_bytes()))))
+        .chain(stream::iter(parts.to_vec()).flat_map(move |cid| read_stream(storage.clone(), cid)))
+        .boxed();
+
+    let cid = app_state.storage.put(&session.file_name, blob).await?;
+    upload_file_request.cid = cid.clone();
+
+    tracing::info!("File uploaded to server");
+
+    let saved = async {
+        let mut file = FileCollection::from(upload_file_request);
+        file.id = session.id;
+        let share_code = file
+            .share_code
+            .clone()
+            .ok_or_else(|| AppError::Internal("File without a share code".to_string()))?;
+        let uploaded_file_result = app_state.file_collection.insert_one(file).await?;
+
+        tracing::info!("File metadata uploaded to database");
+
+        Ok((
+            object_id_to_str(&uploaded_file_result.inserted_id.as_object_id())?,
+            share_code,
+        ))
+    }
+    .await;
+
+    // Don't leave a blob behind that no file points to
+    if saved.is_err() {
+        if let Err(e) = app_state.storage.delete(&cid).await {
+            tracing::error!("Error removing unsaved blob: {:?}", e);
+        }
+    }
+
+    saved
+}
+
+/// Removes the stored parts of an upload.
+///
+/// Parts that are already gone count as removed, and a part that can't be removed
+/// doesn't stop the others from being removed.
+///
+/// # Returns
+/// * `Ok(())` once no part is left.
+/// * `Err(AppError)` with the first error, after every part was tried.
+pub async fn delete_parts(app_state: &AppState, parts: &[String]) -> Result<(), AppError> {
+    let mut result = Ok(());
+
+    for cid in parts {
+        if let Err(e) = app_state.storage.delete(cid).await {
+            if let Ok(false) = app_state.storage.exists(cid).await {
+                continue;
+            }
+
+            tracing::error!("Error deleting part {}: {:?}", cid, e);
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+
+    result
+}
+
+/// Destroys the data key of an upload, and its shred key unless a file was saved with it.
+pub async fn destroy_session_keys(
+    app_state: &AppState,
+    session: &UploadSessionCollection,
+    is_saved: bool,
+) -> Result<(), AppError> {
+    app_state
+        .key_provider
+        .destroy(&UploadSessionCollection::data_key_id(&session.shred_key))
+        .await?;
+
+    if !is_saved {
+        app_state
+            .key_provider
+            .destroy(&session.shred_key.key_id)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetches an unexpired upload session of a user.
+async fn find_session(
+    app_state: &AppState,
+    upload_id: &str,
+    user_id: ObjectId,
+) -> Result<UploadSessionCollection, AppError> {
+    let session_id = str_to_object_id(upload_id)?;
+
+    app_state
+        .upload_session_collection
+        .find_one(doc! {"_id": session_id, "user_id": user_id})
+        .await?
+        .filter(|session| session.session_expires_at > Utc::now())
+        .ok_or_else(|| AppError::NotFound("No such upload exists!".to_string()))
+}
+
+/// Parses the v2 header stored with an upload session.
+fn session_header(session: &UploadSessionCollection) -> Result<FileHeader, AppError> {
+    hex::decode(&session.file_header)
+        .ok()
+        .and_then(|bytes| FileHeader::parse(&bytes).ok().flatten())
+        .ok_or_else(|| AppError::Internal("Invalid upload session header".to_string()))
+}
+
+/// Reads a required numeric tus header.
+fn header_u64(headers: &HeaderMap, name: &str) -> Result<u64, AppError> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| AppError::BadRequest(format!("Missing or invalid `{}` header", name)))
+}
+
+/// Builds a response without a body.
+fn empty_response<I>(status: StatusCode, headers: I) -> Result<Response<Body>, AppError>
+where
+    I: IntoIterator<Item = (&'static str, String)>,
+{
+    let mut response_builder = Response::builder().status(status);
+    for (name, value) in headers {
+        response_builder = response_builder.header(
+            name,
+            HeaderValue::from_str(&value)
+                .map_err(|e| AppError::Internal(format!("Invalid header value: {}", e)))?,
+        );
+    }
+
+    response_builder
+        .body(Body::empty())
+        .map_err(|e| AppError::Internal(format!("Error in upload : {}", e)))
+}
@@ -69,7 +69,7 @@ pub async fn register_user(
         .user_collection
         .insert_one(user)
         .await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
 
     tracing::info!("User Doc: {:?}", user_doc);
 
@@ -160,7 +160,7 @@ pub async fn send_user_verification_email(
     let email_verification_info = TokenInfo {
         token: uuid::Uuid::new_v4().to_string(),
         token_type: TokenType::EmailVerification,
-        user_id: user.id.clone(),
+        user_id: user.id,
     };
 
     let token = TokenCollection::try_from(email_verification_info.clone())?;
@@ -169,7 +169,7 @@ pub async fn send_user_verification_email(
         .token_collection
         .insert_one(token)
         .await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
 
     tracing::info!("Token Doc: {:?}", token_doc);
 
@@ -265,7 +265,7 @@ pub async fn verify_user(
     }
 
     // Check if token is correct
-    let is_valid_token = verify_secret(&token.hashed_token, &verification_token)?;
+    let is_valid_token = verify_secret(&token.hashed_token, verification_token)?;
     if !is_valid_token {
         return Err(AppError::BadRequest("Invalid token provided!".to_string()));
     }
@@ -441,7 +441,7 @@ pub async fn forgot_password(
         .token_collection
         .insert_one(token)
         .await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
 
     tracing::info!("Forgot password token doc: {:?}", token_doc);
 
@@ -518,7 +518,7 @@ pub async fn reset_password(
     }
 
     // Convert user_id into ObjectId
-    let user_id = str_to_object_id(&user_id.to_string())?;
+    let user_id = str_to_object_id(user_id)?;
 
     // Find appropriate token
     let token = app_state
@@ -545,7 +545,7 @@ pub async fn reset_password(
     }
 
     // Check if token is correct
-    let is_valid_token = verify_secret(&token.hashed_token, &forgot_password_token)?;
+    let is_valid_token = verify_secret(&token.hashed_token, forgot_password_token)?;
     if !is_valid_token {
         return Err(AppError::BadRequest("Invalid token provided!".to_string()));
     }
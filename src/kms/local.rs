@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit, Nonce,
+};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use super::KeyProvider;
+use crate::error::AppError;
+
+/// Size of the AES-GCM nonce prepended to every wrapped key.
+const NONCE_SIZE: usize = 12;
+
+/// Wraps keys with a master key read from a local keyfile.
+///
+/// Every key is wrapped under the master key mixed with a random secret of its own,
+/// kept as a file in the key store directory. Destroying a key removes that file, so
+/// its wrapped form is useless from then on, even if a database backup still holds it.
+pub struct LocalKeyProvider {
+    master_key: [u8; 32],
+    key_store_path: PathBuf,
+}
+
+impl LocalKeyProvider {
+    pub fn new(master_key: [u8; 32], key_store_path: impl Into<PathBuf>) -> Self {
+        LocalKeyProvider {
+            master_key,
+            key_store_path: key_store_path.into(),
+        }
+    }
+
+    /// Reads the master key from a keyfile holding 64 hex characters.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the keyfile.
+    /// * `key_store_path` - Directory the secrets of the wrapped keys are kept in.
+    ///
+    /// # Returns
+    /// * `Ok(LocalKeyProvider)` if the file holds a valid 256-bit key.
+    /// * `Err(AppError)` if it can't be read or parsed.
+    pub fn from_file(path: &str, key_store_path: &str) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Internal(format!("Error reading master key: {}", e)))?;
+
+        let master_key: [u8; 32] = hex::decode(contents.trim())
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| AppError::Internal("Invalid master key".to_string()))?;
+
+        Ok(LocalKeyProvider::new(master_key, key_store_path))
+    }
+
+    /// Path of the secret of a key; ids are hashed so they can't name other files.
+    fn secret_path(&self, key_id: &str) -> PathBuf {
+        self.key_store_path
+            .join(hex::encode(Sha256::digest(key_id.as_bytes())))
+    }
+
+    /// The cipher a key is wrapped with: the master key mixed with the key's secret.
+    fn cipher(&self, secret: &[u8]) -> Aes256Gcm {
+        let wrapping_key = Sha256::new()
+            .chain_update(self.master_key)
+            .chain_update(secret)
+            .finalize();
+
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key))
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    /// Stores a new secret for the key and encrypts the key with AES-256-GCM; the
+    /// output is `hex(nonce + ciphertext)`.
+    async fn wrap(&self, key_id: &str, key: &[u8; 32]) -> Result<String, AppError> {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+
+        fs::create_dir_all(&self.key_store_path)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error creating key store: {}", e)))?;
+        fs::write(self.secret_path(key_id), hex::encode(secret))
+            .await
+            .map_err(|e| AppError::Internal(format!("Error storing key: {}", e)))?;
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let wrapped_key = self
+            .cipher(&secret)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: key,
+                    aad: key_id.as_bytes(),
+                },
+            )
+            .map_err(|e| AppError::Internal(format!("Error in wrapping key: {}", e)))?;
+
+        Ok(hex::encode([nonce.as_slice(), &wrapped_key].concat()))
+    }
+
+    async fn unwrap(&self, key_id: &str, wrapped_key: &str) -> Result<[u8; 32], AppError> {
+        let secret = match fs::read_to_string(self.secret_path(key_id)).await {
+            Ok(secret) => hex::decode(secret.trim())
+                .map_err(|_| AppError::Internal("Invalid stored key".to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::Internal(format!(
+                    "Key {} has been destroyed",
+                    key_id
+                )))
+            }
+            Err(e) => return Err(AppError::Internal(format!("Error reading key: {}", e))),
+        };
+
+        let wrapped_key = hex::decode(wrapped_key)
+            .ok()
+            .filter(|wrapped_key| wrapped_key.len() > NONCE_SIZE)
+            .ok_or_else(|| AppError::Internal("Invalid wrapped key".to_string()))?;
+        let (nonce, ciphertext) = wrapped_key.split_at(NONCE_SIZE);
+
+        let key = self
+            .cipher(&secret)
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: key_id.as_bytes(),
+                },
+            )
+            .map_err(|e| AppError::Internal(format!("Error in unwrapping key: {}", e)))?;
+
+        key.try_into()
+            .map_err(|_| AppError::Internal("Invalid key length".to_string()))
+    }
+
+    /// Removes the secret of the key; a key that is already gone counts as destroyed.
+    async fn destroy(&self, key_id: &str) -> Result<(), AppError> {
+        match fs::remove_file(self.secret_path(key_id)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(AppError::Internal(format!("Error destroying key: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key store directory removed again when the test ends.
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn test_provider() -> (TestDir, LocalKeyProvider) {
+        let root = std::env::temp_dir().join(format!("fileshare-rs-{}", uuid::Uuid::new_v4()));
+        (TestDir(root.clone()), LocalKeyProvider::new([3; 32], root))
+    }
+
+    #[tokio::test]
+    async fn unwraps_what_it_wrapped() {
+        let (_dir, provider) = test_provider();
+        let key = [9; 32];
+
+        let wrapped_key = provider.wrap("file-key", &key).await.unwrap();
+
+        assert_eq!(
+            provider.unwrap("file-key", &wrapped_key).await.unwrap(),
+            key
+        );
+        // Only under its own id
+        assert!(provider.unwrap("other-key", &wrapped_key).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cannot_unwrap_a_destroyed_key() {
+        let (_dir, provider) = test_provider();
+
+        let wrapped_key = provider.wrap("file-key", &[9; 32]).await.unwrap();
+        let other_wrapped_key = provider.wrap("other-key", &[5; 32]).await.unwrap();
+        provider.destroy("file-key").await.unwrap();
+
+        // Even with the wrapped key kept, e.g. in a backup, and the same master key
+        let provider = LocalKeyProvider::new([3; 32], provider.key_store_path.clone());
+        assert!(provider.unwrap("file-key", &wrapped_key).await.is_err());
+        assert_eq!(
+            provider
+                .unwrap("other-key", &other_wrapped_key)
+                .await
+                .unwrap(),
+            [5; 32]
+        );
+
+        // Destroying it again is fine
+        provider.destroy("file-key").await.unwrap();
+    }
+}
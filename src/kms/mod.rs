@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use strum_macros::{Display, EnumString};
+
+use crate::{config::AppConfig, error::AppError};
+
+pub mod local;
+
+/// Key providers that can be selected with the `KEY_PROVIDER` env variable.
+#[derive(Debug, Clone, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum KeyProviderKind {
+    Local,
+}
+
+/// Holds the server-side master key that wraps the shred key of every file.
+///
+/// A file's content can only be decrypted while its shred key can be unwrapped, so
+/// destroying it (crypto-shredding) makes the stored ciphertext unrecoverable even
+/// if the blob itself survives in storage.
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// Wraps a shred key under the master key.
+    ///
+    /// # Arguments
+    /// * `key_id` - A unique identifier of the key; bound to the wrapped form.
+    /// * `key` - The shred key to wrap.
+    ///
+    /// # Returns
+    /// * `Ok(String)` with the opaque wrapped key, to be stored with the file.
+    async fn wrap(&self, key_id: &str, key: &[u8; 32]) -> Result<String, AppError>;
+
+    /// Unwraps a shred key wrapped by [`KeyProvider::wrap`].
+    async fn unwrap(&self, key_id: &str, wrapped_key: &str) -> Result<[u8; 32], AppError>;
+
+    /// Destroys a key, so its wrapped form can never be unwrapped again.
+    ///
+    /// Callers remove the wrapped key from the file's metadata as well.
+    async fn destroy(&self, key_id: &str) -> Result<(), AppError>;
+}
+
+/// Builds the key provider selected in the application config.
+///
+/// # Panics
+/// If the master key of the selected provider can not be loaded.
+pub fn load_key_provider(app_config: &AppConfig) -> Arc<dyn KeyProvider> {
+    match app_config.key_provider {
+        KeyProviderKind::Local => Arc::new(
+            local::LocalKeyProvider::from_file(
+                &app_config.master_key_file,
+                &app_config.key_store_path,
+            )
+            .expect("MASTER_KEY_FILE should contain a 256-bit key as 64 hex characters"),
+        ),
+    }
+}
@@ -2,7 +2,10 @@ use std::net::SocketAddr;
 
 use axum::{body::Body, extract::Request, response::Redirect, routing::get, Extension, Router};
 use config::{AppConfig, AppState};
-use routes::{file::get_file_routes, health::get_health_routes, user::get_user_routes};
+use routes::{
+    admin::get_admin_routes, file::get_file_routes, health::get_health_routes,
+    share::get_share_routes, user::get_user_routes,
+};
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::FmtSubscriber;
@@ -13,8 +16,10 @@ mod cron;
 mod dtos;
 mod error;
 mod handler;
+mod kms;
 mod models;
 mod routes;
+mod storage;
 mod utils;
 
 #[tokio::main]
@@ -36,6 +41,8 @@ async fn main() {
         .nest("/health", get_health_routes())
         .nest("/user", get_user_routes())
         .nest("/file", get_file_routes())
+        .nest("/d", get_share_routes())
+        .nest("/admin", get_admin_routes())
         .layer(Extension(app_state.clone()))
         .layer(
             TraceLayer::new_for_http()
@@ -49,11 +56,11 @@ async fn main() {
 
     tracing::info!("Server started on: {} 🚀", listener.local_addr().unwrap());
 
-    cron::auto_delete_file_from_server(app_state)
+    cron::start_cron_jobs(app_state)
         .await
         .expect("Cron Job error");
 
-    tracing::info!("Started Cron Job 🏁");
+    tracing::info!("Started Cron Jobs 🏁");
 
     // Run server
     axum::serve(
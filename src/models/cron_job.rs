@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+/// A background job, run on its schedule by `start_cron_jobs`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CronJob {
+    /// Records expiries and used-up downloads and purges files, see `sweep_files`.
+    FileSweep,
+    /// Removes email verification and password reset tokens once they expired.
+    TokenCleanup,
+    /// Removes tus uploads that were not completed in time, along with their parts.
+    UploadSessionCleanup,
+}
+
+impl CronJob {
+    pub const ALL: [CronJob; 3] = [
+        CronJob::FileSweep,
+        CronJob::TokenCleanup,
+        CronJob::UploadSessionCleanup,
+    ];
+}
+
+/// The state of a job, shared by every instance of the server.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CronJobCollection {
+    #[serde(rename = "_id")]
+    pub job: CronJob,
+
+    // Cron expression with seconds, from the config
+    pub schedule: String,
+    // Paused jobs skip their scheduled runs, but can still be triggered
+    pub is_paused: bool,
+    // Set while an instance runs the job, so runs never overlap
+    pub running_since: Option<DateTime<Utc>>,
+
+    pub last_run: Option<JobRun>,
+    // Kept until the next failure, so later successful runs don't hide it
+    pub last_failure: Option<JobRun>,
+}
+
+/// What started a run of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobTrigger {
+    Schedule,
+    /// An admin, through `POST /admin/jobs/{job}/run`.
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// One run of a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    pub trigger: JobTrigger,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub outcome: JobOutcome,
+    pub error: Option<String>,
+    // What the job got done, e.g. `{"purged": 3}`; empty if it failed
+    pub items: BTreeMap<String, u64>,
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// How long `Range` requests from the same client can continue an earlier download.
+pub const DOWNLOAD_SESSION_MINUTES: i64 = 60;
+
+/// How a download went.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    /// Bytes are being served.
+    Started,
+    /// Every requested byte was served.
+    Completed,
+    /// Serving the file failed, or the client went away.
+    Failed,
+}
+
+/// One download (or resumed part of one) of a file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadCollection {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub file_id: ObjectId,
+    // The file of a bundle that was served; `None` for single files and ZIPs
+    #[serde(default)]
+    pub member: Option<String>,
+    // The share link that served the download; `None` for the file's own link
+    #[serde(default)]
+    pub share_link_id: Option<ObjectId>,
+    // Owner of the file, for account-wide history and stats
+    pub user_id: ObjectId,
+
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+
+    // Stored as a BSON date so downloads can be sorted and aggregated by time
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub downloaded_at: DateTime<Utc>,
+
+    pub status: DownloadStatus,
+    // First byte of the file that was served, so a resume can tell where this stopped
+    #[serde(default)]
+    pub range_start: u64,
+    pub bytes_sent: u64,
+    pub duration_ms: Option<u64>,
+    // Continues an earlier download and did not use up a download
+    pub is_resumed: bool,
+}
+
+impl DownloadCollection {
+    pub fn new(
+        file_id: ObjectId,
+        share_link_id: Option<ObjectId>,
+        user_id: ObjectId,
+        ip_address: String,
+        user_agent: Option<String>,
+        is_resumed: bool,
+    ) -> Self {
+        DownloadCollection {
+            id: None,
+            file_id,
+            member: None,
+            share_link_id,
+            user_id,
+            ip_address,
+            user_agent,
+            downloaded_at: Utc::now(),
+            status: DownloadStatus::Started,
+            range_start: 0,
+            bytes_sent: 0,
+            duration_ms: None,
+            is_resumed,
+        }
+    }
+}
+
+/// Start of the window in which a client's downloads form one download session.
+pub fn download_session_start() -> DateTime<Utc> {
+    Utc::now() - Duration::minutes(DOWNLOAD_SESSION_MINUTES)
+}
+
+/// Kind of client a download came from, guessed from its `User-Agent`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAgentFamily {
+    Browser,
+    /// curl, wget and other command-line clients.
+    Cli,
+    /// Crawlers and link previews.
+    Bot,
+    /// Anything else, including no `User-Agent` at all.
+    Other,
+}
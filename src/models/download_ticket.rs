@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// How long a download ticket can be redeemed.
+pub const DOWNLOAD_TICKET_SECONDS: i64 = 60;
+
+/// A short-lived, single-use grant to download a file, issued once its password was checked.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadTicketCollection {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    // See `hash_token`; the ticket itself is only returned to the client
+    pub ticket_hash: String,
+
+    pub file_id: ObjectId,
+    // The share link the ticket was issued through, if any
+    pub share_link_id: Option<ObjectId>,
+
+    // Only this client may redeem the ticket
+    pub ip_address: String,
+
+    // The content key of the file (or the password of an older file), sealed with
+    // the ticket. `None` for `Client` mode files.
+    pub sealed_key: Option<String>,
+    // The sealed key is the password of an older file rather than its content key
+    pub is_password: bool,
+
+    // The file of a bundle to download; `None` downloads a bundle as a ZIP
+    pub member: Option<String>,
+
+    // Stored as a BSON date so MongoDB can expire tickets nobody redeemed
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub expires_at: DateTime<Utc>,
+}
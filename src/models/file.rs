@@ -1,8 +1,24 @@
 use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
 
-use crate::dtos::file::UploadFileRequest;
+use crate::{
+    dtos::file::{sanitize_file_name, sanitize_mime_type, UploadFileRequest},
+    utils::share_code::generate_share_code,
+};
+
+/// Who encrypts a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum EncryptionMode {
+    /// The server encrypts the upload with the file password and decrypts it on download.
+    #[default]
+    Server,
+    /// The client uploads ciphertext; the server never sees a key and serves the blob as-is.
+    Client,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct FileCollection {
@@ -10,51 +26,281 @@ pub struct FileCollection {
     pub id: Option<ObjectId>,
 
     pub user_id: ObjectId,
+    // Random code the file is shared by, see `share_code_filter`. `None` for files
+    // stored before share codes existed, which are still shared by their id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_code: Option<String>,
 
     pub name: String,
     pub size: u64, // bytes
+    // Empty for bundles, whose files are stored one by one
     pub cid: String,
     pub mime_type: String,
 
+    // The files of a bundle, see `BundleMember`. Empty for single files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<BundleMember>,
+
     pub uploaded_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
 
     pub max_downloads: u8,
     pub download_count: u8,
-    pub downloads: Vec<DownloadEntry>,
+
+    // Hides the name and MIME type from `GET /file/{id}/info`
+    #[serde(default)]
+    pub hide_metadata: bool,
+
+    // Whether recipients need a password (rather than the key from the share link)
+    #[serde(default = "default_password_protected")]
+    pub password_protected: bool,
+
+    // Files stored before client-side encryption existed were encrypted by the server
+    #[serde(default)]
+    pub encryption_mode: EncryptionMode,
+    // Opaque (client-encrypted) metadata of `Client` mode files
+    pub client_metadata: Option<String>,
+    // The data key of the file, wrapped once per password. Empty for `Client` mode
+    // files and for files stored before data keys existed.
+    #[serde(default)]
+    pub key_slots: Vec<KeySlot>,
+    // Mixed into the data key; destroyed when the file is purged
+    pub shred_key: Option<ShredKey>,
+
+    // Where the file is in its life; files stored before it existed are `Active`
+    #[serde(default)]
+    pub status: FileStatus,
+    // Stored as an RFC 3339 string like the other dates of a file, see `date_to_bson`
+    pub status_changed_at: Option<DateTime<Utc>>,
+    // Every status the file went through, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_history: Vec<StatusChange>,
+
+    // Set once the file has been purged
+    pub purge: Option<PurgeRecord>,
+
+    // Part of every signed URL of the file; bumped to revoke them all
+    #[serde(default)]
+    pub signed_url_version: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct DownloadEntry {
-    ip_address: String,
-    user_agent: Option<String>,
-    downloaded_at: DateTime<Utc>,
+/// Where a file is in its life.
+///
+/// An `Active` file becomes `Expired`, `Exhausted` or `Revoked` (an exhausted one can
+/// still expire or be revoked). The sweeper then marks it `Purging` while it destroys
+/// its keys and blobs, and `Purged` once they are gone. Owners can bring an expired
+/// or exhausted file back by raising its expiry or download limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// Can be downloaded.
+    #[default]
+    Active,
+    /// `expires_at` has passed.
+    Expired,
+    /// Every allowed download was used; share links of the file may still work.
+    Exhausted,
+    /// The owner stopped every download.
+    Revoked,
+    /// Keys and blobs are being destroyed.
+    Purging,
+    /// Purged from storage, see `PurgeRecord`.
+    // Listings used to call it `deleted`
+    #[serde(alias = "deleted")]
+    Purged,
 }
 
-impl DownloadEntry {
-    pub fn new(ip_address: String, user_agent: Option<String>) -> Self {
-        DownloadEntry {
-            ip_address,
-            user_agent,
-            downloaded_at: Utc::now(),
+impl FileStatus {
+    /// The statuses a file may move to this one from.
+    pub fn predecessors(self) -> &'static [FileStatus] {
+        match self {
+            FileStatus::Active => &[FileStatus::Expired, FileStatus::Exhausted],
+            FileStatus::Expired => &[FileStatus::Active, FileStatus::Exhausted],
+            FileStatus::Exhausted => &[FileStatus::Active],
+            FileStatus::Revoked => &[
+                FileStatus::Active,
+                FileStatus::Expired,
+                FileStatus::Exhausted,
+            ],
+            // Retried if a sweep failed half-way
+            FileStatus::Purging => &[
+                FileStatus::Expired,
+                FileStatus::Exhausted,
+                FileStatus::Revoked,
+                FileStatus::Purging,
+            ],
+            FileStatus::Purged => &[FileStatus::Purging],
         }
     }
 }
 
+/// When a file entered a status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: FileStatus,
+    pub changed_at: DateTime<Utc>,
+}
+
+impl FileCollection {
+    /// Whether the file is a bundle of several files, downloaded one by one or as a ZIP.
+    pub fn is_bundle(&self) -> bool {
+        !self.members.is_empty()
+    }
+
+    /// The blobs the file is stored as.
+    pub fn blobs(&self) -> Vec<&str> {
+        match self.is_bundle() {
+            true => self
+                .members
+                .iter()
+                .map(|member| member.cid.as_str())
+                .collect(),
+            false => vec![self.cid.as_str()],
+        }
+    }
+
+    /// The status of the file, including an expiry or the last download that was not
+    /// recorded yet.
+    pub fn status(&self) -> FileStatus {
+        match self.status {
+            // Purged before statuses were stored
+            FileStatus::Active if self.purge.is_some() => FileStatus::Purged,
+            FileStatus::Active | FileStatus::Exhausted if self.expires_at <= Utc::now() => {
+                FileStatus::Expired
+            }
+            FileStatus::Active if self.download_count >= self.max_downloads => {
+                FileStatus::Exhausted
+            }
+            status => status,
+        }
+    }
+}
+
+/// One file of a bundle, encrypted with the bundle's key but stored on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleMember {
+    // Relative path within the bundle, e.g. `docs/report.pdf`
+    pub path: String,
+    pub size: u64, // bytes
+    pub cid: String,
+    pub mime_type: String,
+}
+
+/// The shred key of a file, wrapped by the server's master key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShredKey {
+    pub key_id: String,
+    pub wrapped_key: String,
+}
+
+/// What happened when a file was purged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeRecord {
+    pub purged_at: DateTime<Utc>,
+    // Whether storage confirmed the blob is gone
+    pub blob_deleted: bool,
+    // Whether the key provider destroyed the shred key, which makes the blob unreadable
+    // anyway
+    pub key_shredded: bool,
+}
+
+/// Files stored before `password_protected` existed may have had a password.
+fn default_password_protected() -> bool {
+    true
+}
+
+/// The data key of a file, wrapped with a key derived from one of its passwords.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeySlot {
+    pub salt: String, // hex
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub nonce: String,       // hex
+    pub wrapped_key: String, // hex
+    // Wraps with a random key from a share link instead of a password
+    #[serde(default)]
+    pub is_share_key: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A file is password-protected unless one of its key slots opens with a share key.
+///
+/// `Client` mode files have no key slots; their key never reaches the server.
+pub fn is_password_protected(key_slots: &[KeySlot]) -> bool {
+    !key_slots.is_empty() && !key_slots.iter().any(|key_slot| key_slot.is_share_key)
+}
+
 impl From<UploadFileRequest> for FileCollection {
     fn from(payload: UploadFileRequest) -> Self {
+        let now = Utc::now();
+
         FileCollection {
             id: None,
             user_id: payload.user_id,
-            name: payload.file_name,
+            share_code: Some(generate_share_code()),
+            name: sanitize_file_name(&payload.file_name),
             size: payload.size,
             cid: payload.cid,
-            mime_type: payload.mime_type,
-            uploaded_at: Utc::now(),
+            mime_type: sanitize_mime_type(&payload.mime_type),
+            members: payload
+                .members
+                .into_iter()
+                .map(|member| BundleMember {
+                    path: sanitize_file_name(&member.path),
+                    mime_type: sanitize_mime_type(&member.mime_type),
+                    ..member
+                })
+                .collect(),
+            uploaded_at: now,
             expires_at: payload.expires_at,
             max_downloads: payload.max_downloads,
             download_count: 0,
-            downloads: vec![],
+            hide_metadata: payload.hide_metadata,
+            password_protected: is_password_protected(&payload.key_slots),
+            encryption_mode: payload.encryption_mode,
+            client_metadata: payload.client_metadata,
+            key_slots: payload.key_slots,
+            shred_key: payload.shred_key,
+            status: FileStatus::Active,
+            status_changed_at: Some(now),
+            status_history: vec![StatusChange {
+                status: FileStatus::Active,
+                changed_at: now,
+            }],
+            purge: None,
+            signed_url_version: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_slot(is_share_key: bool) -> KeySlot {
+        KeySlot {
+            salt: String::new(),
+            m_cost: 0,
+            t_cost: 0,
+            p_cost: 0,
+            nonce: String::new(),
+            wrapped_key: String::new(),
+            is_share_key,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn files_with_a_share_key_are_not_password_protected() {
+        assert!(is_password_protected(&[key_slot(false)]));
+        assert!(is_password_protected(&[key_slot(false), key_slot(false)]));
+
+        assert!(!is_password_protected(&[key_slot(true)]));
+        // Anyone with the share link can open it, whatever other passwords it has
+        assert!(!is_password_protected(&[key_slot(false), key_slot(true)]));
+
+        // `Client` mode files
+        assert!(!is_password_protected(&[]));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod cron_job;
+pub mod download;
+pub mod download_ticket;
 pub mod file;
+pub mod share_link;
 pub mod token;
+pub mod upload;
 pub mod user;
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::models::file::KeySlot;
+
+/// One of possibly many links a file is shared with, each with its own limits.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLinkCollection {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub file_id: ObjectId,
+    pub user_id: ObjectId,
+
+    // Who the link is for, e.g. "for legal"
+    pub label: Option<String>,
+    // See `hash_token`; the token itself is only returned when the link is created
+    pub token_hash: String,
+
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: u8,
+    pub download_count: u8,
+
+    // Whether recipients need the link's password on top of its token
+    pub password_protected: bool,
+    // The file's data key, wrapped with the link's password or else its token.
+    // `None` for `Client` mode files.
+    pub key_slot: Option<KeySlot>,
+
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ShareLinkCollection {
+    /// Whether the link can still be used to download its file.
+    pub fn is_available(&self) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at > Utc::now()
+            && self.download_count < self.max_downloads
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dtos::file::UploadFileRequest,
+    models::file::{KeySlot, ShredKey},
+};
+
+/// How long a tus upload can be resumed after it was created.
+pub const UPLOAD_SESSION_HOURS: i64 = 24;
+
+/// A tus upload that has not received all of its bytes yet.
+///
+/// Every `PATCH` request is encrypted and stored as a separate part; the parts are
+/// joined into one blob and turned into a `FileCollection` once the upload is complete.
+#[derive(Serialize, Deserialize)]
+pub struct UploadSessionCollection {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub user_id: ObjectId,
+
+    pub file_name: String,
+    pub mime_type: String,
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: u8,
+    pub hide_metadata: bool,
+
+    pub upload_length: u64, // bytes
+    pub upload_offset: u64, // bytes
+
+    // The v2 header (hex) the parts are sealed with, and the data key wrapped by the
+    // key provider like the shred key, so the session holds no usable key and the
+    // password itself is never stored.
+    pub file_header: String,
+    pub wrapped_data_key: String,
+    pub key_slot: KeySlot,
+    pub shred_key: ShredKey,
+
+    pub parts: Vec<String>,
+
+    pub created_at: DateTime<Utc>,
+    // Stored as a BSON date so expired sessions can be queried
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub session_expires_at: DateTime<Utc>,
+}
+
+impl UploadSessionCollection {
+    /// Id the data key of an upload is wrapped under, bound to its shred key.
+    pub fn data_key_id(shred_key: &ShredKey) -> String {
+        format!("{}:upload", shred_key.key_id)
+    }
+
+    pub fn new(
+        payload: UploadFileRequest,
+        upload_length: u64,
+        file_header: String,
+        wrapped_data_key: String,
+        key_slot: KeySlot,
+        shred_key: ShredKey,
+    ) -> Self {
+        let now = Utc::now();
+
+        UploadSessionCollection {
+            id: None,
+            user_id: payload.user_id,
+            file_name: payload.file_name,
+            mime_type: payload.mime_type,
+            expires_at: payload.expires_at,
+            max_downloads: payload.max_downloads,
+            hide_metadata: payload.hide_metadata,
+            upload_length,
+            upload_offset: 0,
+            file_header,
+            wrapped_data_key,
+            key_slot,
+            shred_key,
+            parts: vec![],
+            created_at: now,
+            session_expires_at: now + Duration::hours(UPLOAD_SESSION_HOURS),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+
+use crate::{
+    handler::admin::{cron_jobs, pause_cron_job, resume_cron_job, run_cron_job},
+    utils::extractor::ExtractAdmin,
+};
+
+pub fn get_admin_routes() -> Router {
+    // Protected by the admin API key
+    Router::new()
+        .route("/jobs", get(cron_jobs))
+        .route("/jobs/{job}/pause", post(pause_cron_job))
+        .route("/jobs/{job}/resume", post(resume_cron_job))
+        .route("/jobs/{job}/run", post(run_cron_job))
+        .route_layer(middleware::from_extractor::<ExtractAdmin>())
+}
@@ -1,24 +1,88 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
-    routing::{get, post},
+    routing::{delete, get, head, options, patch, post, put},
     Router,
 };
 
 use crate::{
-    handler::file::{download_file, upload_file, user_files},
-    utils::extractor::ExtractAuthAgent,
+    dtos::file::MAX_UPLOAD_SIZE,
+    handler::{
+        file::{
+            add_file_password, change_file_password, create_download_ticket, delete_file,
+            download_file, file_downloads, file_info, remove_file_password, update_file,
+            upload_file, user_files,
+        },
+        lifecycle::revoke_file,
+        share_link::{create_share_link, revoke_share_link, share_links},
+        signed_url::{create_signed_url, revoke_signed_urls, signed_download},
+        stats::{file_stats, user_stats},
+        upload::{
+            append_upload, create_upload, put_file, terminate_upload, upload_offset, upload_options,
+        },
+    },
+    utils::{extractor::ExtractAuthAgent, tus::tus_resumable},
 };
 
+/// Bytes allowed in an upload request on top of the file itself.
+const UPLOAD_FORM_OVERHEAD: usize = 1024 * 1024; // 1 MiB
+
 pub fn get_file_routes() -> Router {
     // Protected routes
     let protected_routes = Router::new()
-        .route("/upload", post(upload_file))
+        .route(
+            "/upload",
+            // Leave room for the multipart framing and the other form fields
+            post(upload_file).layer(DefaultBodyLimit::max(
+                MAX_UPLOAD_SIZE as usize + UPLOAD_FORM_OVERHEAD,
+            )),
+        )
+        .route(
+            "/put/{file_name}",
+            put(put_file).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE as usize)),
+        )
         .route("/user-files", get(user_files))
+        .route("/stats", get(user_stats))
+        .route("/{id}", patch(update_file).delete(delete_file))
+        .route("/{id}/revoke", post(revoke_file))
+        .route("/{id}/downloads", get(file_downloads))
+        .route("/{id}/stats", get(file_stats))
+        .route("/{id}/links", post(create_share_link).get(share_links))
+        .route("/{id}/links/{link_id}", delete(revoke_share_link))
+        .route(
+            "/{id}/signed-urls",
+            post(create_signed_url).delete(revoke_signed_urls),
+        )
+        .route(
+            "/{id}/passwords",
+            post(add_file_password)
+                .put(change_file_password)
+                .delete(remove_file_password),
+        )
         .route_layer(middleware::from_extractor::<ExtractAuthAgent>());
 
+    // Resumable uploads (tus 1.0), only discovery via OPTIONS is public
+    let tus_routes = Router::new()
+        .route("/uploads", post(create_upload))
+        .route(
+            "/uploads/{id}",
+            head(upload_offset)
+                .patch(append_upload)
+                .delete(terminate_upload)
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE as usize)),
+        )
+        .route_layer(middleware::from_extractor::<ExtractAuthAgent>())
+        .route("/uploads", options(upload_options))
+        .route("/uploads/{id}", options(upload_options))
+        .layer(middleware::from_fn(tus_resumable));
+
     // Public routes
-    let public_routes = Router::new().route("/download", post(download_file));
+    let public_routes = Router::new()
+        .route("/download", post(create_download_ticket))
+        .route("/download/{ticket}", get(download_file))
+        .route("/signed/{id}", get(signed_download))
+        .route("/{id}/info", get(file_info));
 
-    // Combine both
-    protected_routes.merge(public_routes)
+    // Combine all
+    protected_routes.merge(tus_routes).merge(public_routes)
 }
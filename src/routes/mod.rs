@@ -1,3 +1,5 @@
-pub mod health;
+pub mod admin;
 pub mod file;
+pub mod health;
+pub mod share;
 pub mod user;
@@ -0,0 +1,10 @@
+use axum::{routing::get, Router};
+
+use crate::handler::file::direct_download;
+
+pub fn get_share_routes() -> Router {
+    // Public routes; the path after the share picks a file of a bundle
+    Router::new()
+        .route("/{share}", get(direct_download))
+        .route("/{share}/{*file_name}", get(direct_download))
+}
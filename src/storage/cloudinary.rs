@@ -0,0 +1,435 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::TryStreamExt;
+use reqwest::{multipart, Client};
+use sha1::{Digest, Sha1};
+
+use super::{slice_range, BlobStream, StorageBackend};
+use crate::error::AppError;
+
+/// Size of every chunk (but the last) of a chunked upload. Cloudinary requires at least 5 MB.
+const UPLOAD_CHUNK_SIZE: usize = 20_000_000; // 20 MB
+
+/// Stores encrypted blobs as raw assets on Cloudinary.
+///
+/// The `cid` of a blob is its public `secure_url`.
+pub struct CloudinaryStorage {
+    cloud_name: String,
+    api_key: String,
+    api_secret: String,
+    client: Client,
+}
+
+impl CloudinaryStorage {
+    pub fn new(cloud_name: String, api_key: String, api_secret: String) -> Self {
+        CloudinaryStorage {
+            cloud_name,
+            api_key,
+            api_secret,
+            client: Client::new(),
+        }
+    }
+
+    /// Signs request params the way Cloudinary expects: sorted `key=value` pairs
+    /// joined by `&`, followed by the API secret, hashed with SHA-1.
+    ///
+    /// # Arguments
+    /// * `params` - The params to sign, sorted by key.
+    ///
+    /// # Returns
+    /// * `String` containing the hex encoded signature.
+    fn sign(&self, params: &BTreeMap<&str, String>) -> String {
+        let mut to_sign = String::new();
+        for (k, v) in params {
+            to_sign.push_str(&format!("{}={}&", k, v));
+        }
+        to_sign.pop(); // remove trailing '&'
+        to_sign.push_str(&self.api_secret);
+
+        let signature = Sha1::digest(to_sign.as_bytes());
+        hex::encode(signature)
+    }
+
+    /// Sends one upload request to Cloudinary.
+    ///
+    /// # Arguments
+    /// * `file_name` - A base name to include in the output file name.
+    /// * `chunk` - The bytes to send.
+    /// * `chunked` - The upload ID and `Content-Range` when this is part of a chunked upload.
+    ///
+    /// # Returns
+    /// * `Ok(serde_json::Value)` containing Cloudinary's response.
+    /// * `Err(AppError)` if the request fails.
+    async fn upload_chunk(
+        &self,
+        file_name: &str,
+        chunk: Vec<u8>,
+        chunked: Option<(&str, String)>,
+    ) -> Result<serde_json::Value, AppError> {
+        let timestamp = Utc::now().timestamp().to_string();
+
+        // Params to sign
+        let mut params = BTreeMap::new();
+        params.insert("timestamp", timestamp.clone());
+
+        // Generate signature
+        let signature_hex = self.sign(&params);
+
+        // Create multipart form
+        let part = multipart::Part::bytes(chunk)
+            .file_name(file_name.to_string())
+            .mime_str("application/octet-stream")
+            .map_err(|e| AppError::Internal(format!("Error creating file stream: {e}")))?;
+
+        let form = multipart::Form::new()
+            .part("file", part)
+            .text("api_key", self.api_key.clone())
+            .text("timestamp", timestamp)
+            .text("signature", signature_hex);
+
+        // POST to Cloudinary
+        let upload_url = format!(
+            "https://api.cloudinary.com/v1_1/{}/auto/upload",
+            self.cloud_name
+        );
+
+        let mut request = self.client.post(&upload_url).multipart(form);
+        if let Some((upload_id, content_range)) = chunked {
+            request = request
+                .header("X-Unique-Upload-Id", upload_id)
+                .header("Content-Range", content_range);
+        }
+
+        let res = request
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error uploading file stream: {e}")))?;
+
+        if res.status().is_success() {
+            res.json()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error in parsing json response: {e}")))
+        } else {
+            let text = res
+                .text()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error in parsing response: {e}")))?;
+            tracing::error!("Error in uploading file to cloud: {}", text);
+            Err(AppError::Internal(text))
+        }
+    }
+}
+
+/// Extracts the public ID from a Cloudinary file URL.
+///
+/// The public ID is assumed to be the last segment of the URL (after the final `/`).
+///
+/// # Arguments
+/// * `file_url` - A string slice representing the full Cloudinary file URL.
+///
+/// # Returns
+/// * `String` containing the extracted public ID.
+fn extract_public_id(file_url: &str) -> String {
+    let mut response = String::new();
+
+    for char in file_url.chars().rev() {
+        if char == '/' {
+            break;
+        }
+        response.push(char);
+    }
+
+    response.chars().rev().collect()
+}
+
+#[async_trait]
+impl StorageBackend for CloudinaryStorage {
+    /// Saves an encrypted file to the cloudinary
+    ///
+    /// Files larger than `UPLOAD_CHUNK_SIZE` are sent with Cloudinary's chunked upload
+    /// API, so at most two chunks are held in memory at a time.
+    ///
+    /// # Arguments
+    /// * `file_name` - A base name to include in the output file name.
+    /// * `data` - The encrypted file bytes.
+    ///
+    /// # Returns
+    /// * `Ok(String)` containing the secure URL of the saved file.
+    /// * `Err(AppError)` if the upload fails.
+    async fn put<'a>(&self, file_name: &str, mut data: BlobStream<'a>) -> Result<String, AppError> {
+        let upload_id = uuid::Uuid::new_v4().simple().to_string();
+
+        let mut buffer = Vec::new();
+        let mut pending: Option<Vec<u8>> = None;
+        let mut sent = 0;
+
+        while let Some(chunk) = data.try_next().await? {
+            buffer.extend_from_slice(&chunk);
+
+            while buffer.len() >= UPLOAD_CHUNK_SIZE {
+                let rest = buffer.split_off(UPLOAD_CHUNK_SIZE);
+                let ready = std::mem::replace(&mut buffer, rest);
+
+                // Only the last chunk may carry the total size, so keep one chunk in hand
+                if let Some(chunk) = pending.replace(ready) {
+                    let range = format!("bytes {}-{}/-1", sent, sent + chunk.len() - 1);
+                    sent += chunk.len();
+                    self.upload_chunk(file_name, chunk, Some((&upload_id, range)))
+                        .await?;
+                }
+            }
+        }
+
+        let response = match pending {
+            // Small enough for a single request
+            None => self.upload_chunk(file_name, buffer, None).await?,
+            Some(chunk) => {
+                let mut last = chunk;
+                if !buffer.is_empty() {
+                    let range = format!("bytes {}-{}/-1", sent, sent + last.len() - 1);
+                    sent += last.len();
+                    self.upload_chunk(file_name, last, Some((&upload_id, range)))
+                        .await?;
+                    last = buffer;
+                }
+
+                let total = sent + last.len();
+                let range = format!("bytes {}-{}/{}", sent, total - 1, total);
+                self.upload_chunk(file_name, last, Some((&upload_id, range)))
+                    .await?
+            }
+        };
+
+        let secure_url = response["secure_url"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        tracing::info!("File has been uploaded to cloud");
+        Ok(secure_url)
+    }
+
+    /// Reads raw file data from its public Cloudinary URL.
+    ///
+    /// # Arguments
+    /// * `cid` - The URL of the file to download.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` containing the file's byte contents if the request is successful.
+    /// * `Err(AppError)` if the HTTP request fails or the response cannot be converted to bytes.
+    async fn get(&self, cid: &str) -> Result<Vec<u8>, AppError> {
+        let response = self
+            .client
+            .get(cid)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in fetching file: {e}")))?;
+
+        if response.status().is_success() {
+            let bytes = response.bytes().await.map_err(|e| {
+                AppError::Internal(format!("Error in converting file data to bytes: {e}"))
+            })?;
+            Ok(bytes.to_vec())
+        } else {
+            Err(AppError::Internal(format!(
+                "Failed to fetch file: {}",
+                response.status()
+            )))
+        }
+    }
+
+    /// Reads part of a file from its public Cloudinary URL with an HTTP `Range` request.
+    ///
+    /// # Arguments
+    /// * `cid` - The URL of the file to download.
+    /// * `offset` - The first byte to read.
+    /// * `length` - The number of bytes to read.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` containing the requested bytes (fewer if the file ends first).
+    /// * `Err(AppError)` if the HTTP request fails.
+    async fn get_range(&self, cid: &str, offset: u64, length: u64) -> Result<Vec<u8>, AppError> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+
+        let response = self
+            .client
+            .get(cid)
+            .header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", offset, offset + length - 1),
+            )
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in fetching file: {e}")))?;
+
+        match response.status() {
+            // The range starts past the end of the file
+            reqwest::StatusCode::RANGE_NOT_SATISFIABLE => Ok(vec![]),
+            status if status.is_success() => {
+                let is_partial = status == reqwest::StatusCode::PARTIAL_CONTENT;
+                let bytes = response.bytes().await.map_err(|e| {
+                    AppError::Internal(format!("Error in converting file data to bytes: {e}"))
+                })?;
+
+                // The CDN may ignore the range and send the whole file
+                Ok(if is_partial {
+                    bytes.to_vec()
+                } else {
+                    slice_range(&bytes, offset, length)
+                })
+            }
+            status => Err(AppError::Internal(format!(
+                "Failed to fetch file: {}",
+                status
+            ))),
+        }
+    }
+
+    /// Deletes a file from Cloudinary using its public URL.
+    ///
+    /// # Arguments
+    /// * `cid` - The Cloudinary file URL to delete.
+    ///
+    /// # Returns
+    /// * `Ok(())` if the deletion is successful.
+    /// * `Err(AppError)` if the request fails or Cloudinary returns an error.
+    async fn delete(&self, cid: &str) -> Result<(), AppError> {
+        let timestamp = Utc::now().timestamp().to_string();
+
+        let public_id = extract_public_id(cid);
+
+        // Prepare URL for deletion - will automatically handle any file type
+        let url = format!(
+            "https://api.cloudinary.com/v1_1/{}/raw/destroy",
+            self.cloud_name
+        );
+
+        // Params to sign
+        let mut params = BTreeMap::new();
+        params.insert("timestamp", timestamp.clone());
+        params.insert("public_id", public_id.clone());
+
+        // Generate signature
+        let signature_hex = self.sign(&params);
+
+        let form = multipart::Form::new()
+            .text("public_id", public_id.clone())
+            .text("api_key", self.api_key.clone())
+            .text("timestamp", timestamp)
+            .text("signature", signature_hex);
+
+        // Perform DELETE request
+        let response = self
+            .client
+            .post(&url)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error deleting file: {e}")))?;
+
+        if response.status().is_success() {
+            tracing::info!("Successfully deleted: {}", public_id);
+            Ok(())
+        } else {
+            let status = response.status();
+            let body = response
+                .text()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error in parsing response: {e}")))?;
+            tracing::error!("Failed to delete file: {} — {}", status, body);
+            Err(AppError::Internal(format!(
+                "Failed to delete file: {} — {}",
+                status, body
+            )))
+        }
+    }
+
+    /// Checks whether the public URL of a file still resolves.
+    ///
+    /// # Arguments
+    /// * `cid` - The Cloudinary file URL to check.
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the file is present, `Ok(false)` if Cloudinary returns `404`.
+    /// * `Err(AppError)` if the request fails or Cloudinary returns another error.
+    async fn exists(&self, cid: &str) -> Result<bool, AppError> {
+        let response = self
+            .client
+            .head(cid)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in fetching file: {e}")))?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status => Err(AppError::Internal(format!(
+                "Failed to check file: {}",
+                status
+            ))),
+        }
+    }
+
+    /// Lists the secure URLs of all raw assets using the Cloudinary Admin API.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<String>)` containing the secure URL of every stored file.
+    /// * `Err(AppError)` if any page of the listing cannot be fetched.
+    async fn list(&self) -> Result<Vec<String>, AppError> {
+        let url = format!(
+            "https://api.cloudinary.com/v1_1/{}/resources/raw",
+            self.cloud_name
+        );
+
+        let mut files = Vec::new();
+        let mut next_cursor: Option<String> = None;
+
+        loop {
+            let mut request = self
+                .client
+                .get(&url)
+                .basic_auth(&self.api_key, Some(&self.api_secret))
+                .query(&[("max_results", "500")]);
+
+            if let Some(cursor) = &next_cursor {
+                request = request.query(&[("next_cursor", cursor)]);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error listing files: {e}")))?;
+
+            if !response.status().is_success() {
+                return Err(AppError::Internal(format!(
+                    "Failed to list files: {}",
+                    response.status()
+                )));
+            }
+
+            let json: serde_json::Value = response
+                .json()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error in parsing json response: {e}")))?;
+
+            if let Some(resources) = json["resources"].as_array() {
+                files.extend(
+                    resources
+                        .iter()
+                        .filter_map(|r| r["secure_url"].as_str())
+                        .map(str::to_string),
+                );
+            }
+
+            match json["next_cursor"].as_str() {
+                Some(cursor) => next_cursor = Some(cursor.to_string()),
+                None => break,
+            }
+        }
+
+        Ok(files)
+    }
+}
@@ -0,0 +1,235 @@
+use std::{io::SeekFrom, path::PathBuf};
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+use super::{BlobStream, StorageBackend};
+use crate::error::AppError;
+
+/// Stores encrypted blobs as files inside a local directory.
+///
+/// The `cid` of a blob is its file name inside that directory.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    /// Resolves a `cid` to a path inside the storage directory.
+    ///
+    /// # Arguments
+    /// * `cid` - The blob identifier returned by `put`.
+    ///
+    /// # Returns
+    /// * `Ok(PathBuf)` if the identifier is a plain file name.
+    /// * `Err(AppError::BadRequest)` if it could escape the storage directory.
+    fn path_for(&self, cid: &str) -> Result<PathBuf, AppError> {
+        if cid.is_empty() || cid.contains(['/', '\\']) || cid.starts_with('.') {
+            return Err(AppError::BadRequest(format!(
+                "Invalid storage key: {}",
+                cid
+            )));
+        }
+
+        Ok(self.root.join(cid))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    /// Writes an encrypted file into the storage directory under a random name.
+    ///
+    /// # Arguments
+    /// * `_file_name` - Ignored; user-supplied names never reach the filesystem.
+    /// * `data` - The encrypted file, written to disk chunk by chunk.
+    ///
+    /// # Returns
+    /// * `Ok(String)` containing the generated file name.
+    /// * `Err(AppError)` if the stream, directory or file operation fails.
+    async fn put<'a>(
+        &self,
+        _file_name: &str,
+        mut data: BlobStream<'a>,
+    ) -> Result<String, AppError> {
+        fs::create_dir_all(&self.root)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error creating storage directory: {e}")))?;
+
+        let cid = uuid::Uuid::new_v4().to_string();
+        let path = self.path_for(&cid)?;
+
+        let mut file = fs::File::create(&path)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error creating file: {e}")))?;
+
+        let written: Result<(), AppError> = async {
+            while let Some(chunk) = data.try_next().await? {
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Error writing file: {e}")))?;
+            }
+            file.flush()
+                .await
+                .map_err(|e| AppError::Internal(format!("Error writing file: {e}")))
+        }
+        .await;
+
+        // Don't leave a partial blob behind
+        if let Err(e) = written {
+            drop(file);
+            if let Err(remove_error) = fs::remove_file(&path).await {
+                tracing::error!("Error removing partial file: {}", remove_error);
+            }
+            return Err(e);
+        }
+
+        tracing::info!("File has been written to local storage");
+
+        Ok(cid)
+    }
+
+    async fn get(&self, cid: &str) -> Result<Vec<u8>, AppError> {
+        fs::read(self.path_for(cid)?)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in reading file: {e}")))
+    }
+
+    async fn get_range(&self, cid: &str, offset: u64, length: u64) -> Result<Vec<u8>, AppError> {
+        let mut file = fs::File::open(self.path_for(cid)?)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in reading file: {e}")))?;
+
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in reading file: {e}")))?;
+
+        let mut data = Vec::new();
+        file.take(length)
+            .read_to_end(&mut data)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error in reading file: {e}")))?;
+
+        Ok(data)
+    }
+
+    async fn delete(&self, cid: &str) -> Result<(), AppError> {
+        fs::remove_file(self.path_for(cid)?)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error deleting file: {e}")))?;
+
+        tracing::info!("Successfully deleted: {}", cid);
+
+        Ok(())
+    }
+
+    async fn exists(&self, cid: &str) -> Result<bool, AppError> {
+        fs::try_exists(self.path_for(cid)?)
+            .await
+            .map_err(|e| AppError::Internal(format!("Error checking file: {e}")))
+    }
+
+    async fn list(&self) -> Result<Vec<String>, AppError> {
+        let mut entries = match fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(AppError::Internal(format!(
+                    "Error reading storage directory: {e}"
+                )))
+            }
+        };
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| AppError::Internal(format!("Error reading storage directory: {e}")))?
+        {
+            if let Some(name) = entry.file_name().to_str() {
+                files.push(name.to_string());
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use futures::{stream, StreamExt};
+
+    use super::*;
+
+    /// A storage directory removed again when the test ends.
+    struct TestDir(PathBuf);
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn test_storage() -> (TestDir, LocalStorage) {
+        let root = std::env::temp_dir().join(format!("fileshare-rs-{}", uuid::Uuid::new_v4()));
+        (TestDir(root.clone()), LocalStorage::new(root))
+    }
+
+    #[tokio::test]
+    async fn stores_lists_and_deletes_blobs() {
+        let (_dir, storage) = test_storage();
+        assert!(storage.list().await.unwrap().is_empty());
+
+        let pieces = [
+            Ok(Bytes::from_static(b"hello ")),
+            Ok(Bytes::from_static(b"world")),
+        ];
+        let cid = storage
+            .put("../../etc/passwd", stream::iter(pieces).boxed())
+            .await
+            .unwrap();
+
+        assert!(storage.exists(&cid).await.unwrap());
+        assert_eq!(storage.get(&cid).await.unwrap(), b"hello world");
+        assert_eq!(storage.get_range(&cid, 6, 100).await.unwrap(), b"world");
+        assert_eq!(storage.list().await.unwrap(), vec![cid.clone()]);
+
+        storage.delete(&cid).await.unwrap();
+        assert!(!storage.exists(&cid).await.unwrap());
+        assert!(storage.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_outside_the_root() {
+        let (_dir, storage) = test_storage();
+
+        for cid in [
+            "..",
+            "../secret",
+            "a/../../secret",
+            "..\\secret",
+            ".hidden",
+            "",
+        ] {
+            assert!(matches!(
+                storage.get(cid).await,
+                Err(AppError::BadRequest(_))
+            ));
+            assert!(matches!(
+                storage.exists(cid).await,
+                Err(AppError::BadRequest(_))
+            ));
+            assert!(matches!(
+                storage.delete(cid).await,
+                Err(AppError::BadRequest(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use strum_macros::{Display, EnumString};
+
+use crate::{config::AppConfig, error::AppError};
+
+pub mod cloudinary;
+pub mod local;
+pub mod s3;
+
+/// Storage backends that can be selected with the `STORAGE_BACKEND` env variable.
+#[derive(Debug, Clone, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum StorageKind {
+    Cloudinary,
+    Local,
+    S3,
+}
+
+/// A blob flowing through the upload pipeline, one bounded chunk at a time.
+pub type BlobStream<'a> = BoxStream<'a, Result<Bytes, AppError>>;
+
+/// A place where encrypted file blobs are kept.
+///
+/// Every blob is addressed by the identifier returned from [`StorageBackend::put`],
+/// which is what gets persisted as `FileCollection.cid`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Stores a blob and returns the identifier used to address it afterwards.
+    ///
+    /// The blob is consumed chunk by chunk, so backends never hold more than a
+    /// bounded part of it in memory. Nothing is left behind if the stream fails.
+    ///
+    /// # Arguments
+    /// * `file_name` - A base name for the blob; backends may ignore it.
+    /// * `data` - The (already encrypted) bytes to store.
+    async fn put<'a>(&self, file_name: &str, data: BlobStream<'a>) -> Result<String, AppError>;
+
+    /// Reads the full contents of a blob.
+    async fn get(&self, cid: &str) -> Result<Vec<u8>, AppError>;
+
+    /// Reads `length` bytes of a blob starting at `offset`.
+    ///
+    /// Fewer bytes (possibly none) are returned if the blob ends before the range does.
+    async fn get_range(&self, cid: &str, offset: u64, length: u64) -> Result<Vec<u8>, AppError>;
+
+    /// Removes a blob from storage.
+    async fn delete(&self, cid: &str) -> Result<(), AppError>;
+
+    /// Checks whether a blob is still present in storage.
+    async fn exists(&self, cid: &str) -> Result<bool, AppError>;
+
+    /// Lists the identifiers of all blobs held by this backend.
+    #[allow(dead_code)]
+    async fn list(&self) -> Result<Vec<String>, AppError>;
+}
+
+/// Size of the windows `read_range` fetches a blob in.
+const READ_WINDOW_SIZE: u64 = 4 * 1024 * 1024; // 4 MiB
+
+/// Streams a whole blob out of storage in bounded windows.
+///
+/// # Arguments
+/// * `storage` - The backend holding the blob.
+/// * `cid` - The identifier of the blob.
+pub fn read_stream(storage: Arc<dyn StorageBackend>, cid: String) -> BlobStream<'static> {
+    read_range(storage, cid, 0..u64::MAX)
+}
+
+/// Streams a range of a blob out of storage in bounded windows.
+///
+/// The stream ends early if the blob ends before the range does.
+///
+/// # Arguments
+/// * `storage` - The backend holding the blob.
+/// * `cid` - The identifier of the blob.
+/// * `range` - The byte range to read.
+pub fn read_range(
+    storage: Arc<dyn StorageBackend>,
+    cid: String,
+    range: Range<u64>,
+) -> BlobStream<'static> {
+    stream::try_unfold(range, move |range| {
+        let storage = storage.clone();
+        let cid = cid.clone();
+        async move {
+            if range.is_empty() {
+                return Ok(None);
+            }
+
+            let length = (range.end - range.start).min(READ_WINDOW_SIZE);
+            let window = storage.get_range(&cid, range.start, length).await?;
+            if window.is_empty() {
+                return Ok(None);
+            }
+
+            // A short window means the blob ended
+            let next = if window.len() as u64 == length {
+                range.start + length..range.end
+            } else {
+                range.end..range.end
+            };
+            Ok(Some((Bytes::from(window), next)))
+        }
+    })
+    .boxed()
+}
+
+/// Cuts `offset..offset + length` out of a whole blob, for stores that ignore `Range` requests.
+fn slice_range(data: &[u8], offset: u64, length: u64) -> Vec<u8> {
+    let start = (offset as usize).min(data.len());
+    let end = offset.saturating_add(length).min(data.len() as u64) as usize;
+    data[start..end].to_vec()
+}
+
+/// Builds the storage backend selected in the application config.
+///
+/// # Panics
+/// If the credentials required by the selected backend are missing.
+pub fn load_storage_backend(app_config: &AppConfig) -> Arc<dyn StorageBackend> {
+    match app_config.storage_backend {
+        StorageKind::Cloudinary => Arc::new(cloudinary::CloudinaryStorage::new(
+            app_config
+                .cloudinary_cloud_name
+                .clone()
+                .expect("CLOUDINARY_CLOUD_NAME not found in .env"),
+            app_config
+                .cloudinary_api_key
+                .clone()
+                .expect("CLOUDINARY_API_KEY not found in .env"),
+            app_config
+                .cloudinary_api_secret
+                .clone()
+                .expect("CLOUDINARY_API_SECRET not found in .env"),
+        )),
+        StorageKind::Local => Arc::new(local::LocalStorage::new(
+            app_config.local_storage_path.clone(),
+        )),
+        StorageKind::S3 => Arc::new(s3::S3Storage::new(
+            app_config
+                .s3_endpoint
+                .as_deref()
+                .expect("S3_ENDPOINT not found in .env"),
+            app_config.s3_region.clone(),
+            app_config
+                .s3_bucket
+                .clone()
+                .expect("S3_BUCKET not found in .env"),
+            app_config.s3_prefix.clone(),
+            app_config
+                .s3_access_key_id
+                .clone()
+                .expect("S3_ACCESS_KEY_ID not found in .env"),
+            app_config
+                .s3_secret_access_key
+                .clone()
+                .expect("S3_SECRET_ACCESS_KEY not found in .env"),
+        )),
+    }
+}
@@ -60,18 +60,16 @@ impl<'a> EmailInfo<'a> {
         match response.status() {
             StatusCode::OK | StatusCode::CREATED | StatusCode::ACCEPTED => {
                 tracing::info!("Email sent 👍");
-                return Ok(true);
-            }
-            _ => {
-                return Err(AppError::Internal(format!(
-                    "Unable to send your email. Status code was: {}. Body content was: {:?}",
-                    response.status(),
-                    response
-                        .text()
-                        .await
-                        .map_err(|_| "Failed to read response body".to_string())
-                )));
+                Ok(true)
             }
+            _ => Err(AppError::Internal(format!(
+                "Unable to send your email. Status code was: {}. Body content was: {:?}",
+                response.status(),
+                response
+                    .text()
+                    .await
+                    .map_err(|_| "Failed to read response body".to_string())
+            ))),
         }
     }
 
@@ -83,7 +81,7 @@ impl<'a> EmailInfo<'a> {
             self.verification_link
         );
 
-        return (subject, content);
+        (subject, content)
     }
 
     fn forgot_password_template(&self) -> (String, String) {
@@ -94,6 +92,6 @@ impl<'a> EmailInfo<'a> {
             self.verification_link
         );
 
-        return (subject, content);
+        (subject, content)
     }
 }
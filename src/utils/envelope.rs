@@ -0,0 +1,368 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead},
+    Aes256Gcm, Key, KeyInit, Nonce,
+};
+use argon2::{password_hash::rand_core::OsRng, Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::AppError,
+    kms::KeyProvider,
+    models::file::{KeySlot, ShredKey},
+};
+
+/// Most passwords a single file can have.
+pub const MAX_KEY_SLOTS: usize = 5;
+
+/// Size of the random salt of a key slot.
+const SALT_SIZE: usize = 16;
+
+/// Size of the AES-GCM nonce used to wrap a data key.
+const NONCE_SIZE: usize = 12;
+
+/// Generates a random 256-bit data key, which is wrapped by the passwords of one file.
+pub fn generate_data_key() -> [u8; 32] {
+    let mut data_key = [0u8; 32];
+    OsRng.fill_bytes(&mut data_key);
+    data_key
+}
+
+/// Generates a random shred key for a file and wraps it with the master key.
+///
+/// # Returns
+/// * `Ok(([u8; 32], ShredKey))` with the shred key and its wrapped form.
+/// * `Err(AppError)` if the key provider fails.
+pub async fn generate_shred_key(
+    key_provider: &dyn KeyProvider,
+) -> Result<([u8; 32], ShredKey), AppError> {
+    let shred_key = generate_data_key();
+    let key_id = uuid::Uuid::new_v4().to_string();
+    let wrapped_key = key_provider.wrap(&key_id, &shred_key).await?;
+
+    Ok((
+        shred_key,
+        ShredKey {
+            key_id,
+            wrapped_key,
+        },
+    ))
+}
+
+/// Combines the data key and the shred key of a file into the key its contents are
+/// encrypted with, so the file can't be decrypted once either of them is gone.
+///
+/// Files stored before shred keys existed are encrypted with the data key itself.
+pub fn content_key(data_key: &[u8; 32], shred_key: Option<&[u8; 32]>) -> [u8; 32] {
+    let Some(shred_key) = shred_key else {
+        return *data_key;
+    };
+
+    Sha256::new()
+        .chain_update(b"fileshare-rs content key")
+        .chain_update(shred_key)
+        .chain_update(data_key)
+        .finalize()
+        .into()
+}
+
+/// Unwraps the key the contents of a file are encrypted with.
+///
+/// # Arguments
+/// * `key_provider` - Holds the master key wrapping the shred key.
+/// * `key_slots` - The key slots of the file.
+/// * `shred_key` - The wrapped shred key of the file, if it has one.
+/// * `password` - A password of the file.
+///
+/// # Returns
+/// * `Ok([u8; 32])` with the content key.
+/// * `Err(AppError)` if the password is wrong or the shred key can't be unwrapped.
+pub async fn unwrap_content_key(
+    key_provider: &dyn KeyProvider,
+    key_slots: &[KeySlot],
+    shred_key: Option<&ShredKey>,
+    password: &str,
+) -> Result<[u8; 32], AppError> {
+    let (_, data_key) = unwrap_data_key(key_slots, password)?;
+
+    open_content_key(key_provider, &data_key, shred_key).await
+}
+
+/// Combines an already unwrapped data key with the file's shred key into its content key.
+///
+/// # Returns
+/// * `Ok([u8; 32])` with the content key.
+/// * `Err(AppError)` if the shred key can't be unwrapped, e.g. because it was destroyed.
+pub async fn open_content_key(
+    key_provider: &dyn KeyProvider,
+    data_key: &[u8; 32],
+    shred_key: Option<&ShredKey>,
+) -> Result<[u8; 32], AppError> {
+    let shred_key = match shred_key {
+        Some(shred_key) => Some(
+            key_provider
+                .unwrap(&shred_key.key_id, &shred_key.wrapped_key)
+                .await?,
+        ),
+        None => None,
+    };
+
+    Ok(content_key(data_key, shred_key.as_ref()))
+}
+
+/// Wraps the data key of a new file with its password, or else with a random share key.
+///
+/// Files uploaded without a password still get a key nobody else knows: the share key
+/// is returned to the uploader once (to be put into the share link) and never stored.
+///
+/// # Arguments
+/// * `data_key` - The data key of the file.
+/// * `password` - The password chosen by the uploader, if any.
+///
+/// # Returns
+/// * `Ok((KeySlot, Option<String>))` with the key slot and the generated share key.
+/// * `Err(AppError)` if key derivation or wrapping fails.
+pub fn wrap_new_data_key(
+    data_key: &[u8; 32],
+    password: Option<&str>,
+) -> Result<(KeySlot, Option<String>), AppError> {
+    if let Some(password) = password {
+        return Ok((wrap_data_key(data_key, password)?, None));
+    }
+
+    let share_key = generate_share_key();
+
+    let mut key_slot = wrap_data_key(data_key, &share_key)?;
+    key_slot.is_share_key = true;
+
+    Ok((key_slot, Some(share_key)))
+}
+
+/// Generates a random, base64url encoded 256-bit key to be put into a share link.
+pub fn generate_share_key() -> String {
+    let mut share_key = [0u8; 32];
+    OsRng.fill_bytes(&mut share_key);
+    URL_SAFE_NO_PAD.encode(share_key)
+}
+
+/// Seals a file key (or the password of an older file) with a download ticket.
+///
+/// Only the hash of the ticket is stored, so the sealed key is useless without the
+/// ticket itself, which only the client holds.
+///
+/// # Returns
+/// * `Ok(String)` with the hex encoded nonce and sealed key.
+pub fn seal_with_ticket(ticket: &str, key: &[u8]) -> Result<String, AppError> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let sealed_key = ticket_cipher(ticket)
+        .encrypt(Nonce::from_slice(&nonce), key)
+        .map_err(|e| AppError::Internal(format!("Error in sealing file key: {}", e)))?;
+
+    Ok(hex::encode([nonce.as_slice(), &sealed_key].concat()))
+}
+
+/// Opens a key sealed by `seal_with_ticket`.
+pub fn open_with_ticket(ticket: &str, sealed_key: &str) -> Result<Vec<u8>, AppError> {
+    let invalid = || AppError::Internal("Invalid sealed file key".to_string());
+
+    let sealed_key = hex::decode(sealed_key).map_err(|_| invalid())?;
+    if sealed_key.len() <= NONCE_SIZE {
+        return Err(invalid());
+    }
+    let (nonce, sealed_key) = sealed_key.split_at(NONCE_SIZE);
+
+    ticket_cipher(ticket)
+        .decrypt(Nonce::from_slice(nonce), sealed_key)
+        .map_err(|_| invalid())
+}
+
+/// Wraps a data key with a key derived from a password (Argon2id), creating a key slot.
+///
+/// # Arguments
+/// * `data_key` - The data key of the file.
+/// * `password` - The password that should open the file.
+///
+/// # Returns
+/// * `Ok(KeySlot)` to be stored with the file's metadata.
+/// * `Err(AppError)` if key derivation or wrapping fails.
+pub fn wrap_data_key(data_key: &[u8; 32], password: &str) -> Result<KeySlot, AppError> {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut key_slot = KeySlot {
+        salt: hex::encode(salt),
+        m_cost: Params::DEFAULT_M_COST,
+        t_cost: Params::DEFAULT_T_COST,
+        p_cost: Params::DEFAULT_P_COST,
+        nonce: hex::encode(nonce),
+        wrapped_key: String::new(),
+        is_share_key: false,
+        created_at: Utc::now(),
+    };
+
+    let wrapped_key = slot_cipher(&key_slot, password)?
+        .encrypt(Nonce::from_slice(&nonce), data_key.as_ref())
+        .map_err(|e| AppError::Internal(format!("Error in wrapping file key: {}", e)))?;
+
+    key_slot.wrapped_key = hex::encode(wrapped_key);
+    Ok(key_slot)
+}
+
+/// Unwraps the data key of a file with one of its passwords.
+///
+/// # Arguments
+/// * `key_slots` - The key slots of the file.
+/// * `password` - A password of the file.
+///
+/// # Returns
+/// * `Ok((usize, [u8; 32]))` with the index of the slot the password opened and the data key.
+/// * `Err(AppError::Unauthorized)` if the password opens none of the slots.
+pub fn unwrap_data_key(
+    key_slots: &[KeySlot],
+    password: &str,
+) -> Result<(usize, [u8; 32]), AppError> {
+    for (index, key_slot) in key_slots.iter().enumerate() {
+        let nonce = hex::decode(&key_slot.nonce)
+            .ok()
+            .filter(|nonce| nonce.len() == NONCE_SIZE)
+            .ok_or_else(|| AppError::Internal("Invalid key slot nonce".to_string()))?;
+        let wrapped_key = hex::decode(&key_slot.wrapped_key)
+            .map_err(|_| AppError::Internal("Invalid wrapped key".to_string()))?;
+
+        // A wrong password fails authentication of the wrapped key
+        let Ok(data_key) = slot_cipher(key_slot, password)?
+            .decrypt(Nonce::from_slice(&nonce), wrapped_key.as_ref())
+        else {
+            continue;
+        };
+
+        let data_key = data_key
+            .try_into()
+            .map_err(|_| AppError::Internal("Invalid data key length".to_string()))?;
+        return Ok((index, data_key));
+    }
+
+    Err(AppError::Unauthorized("Invalid file password".to_string()))
+}
+
+/// Derives the key sealing the file key of a download ticket. Tickets are random,
+/// so a plain hash is enough.
+fn ticket_cipher(ticket: &str) -> Aes256Gcm {
+    let key: [u8; 32] = Sha256::new()
+        .chain_update(b"fileshare-rs download ticket")
+        .chain_update(ticket.as_bytes())
+        .finalize()
+        .into();
+
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+/// Derives the key-wrapping key of a slot from a password.
+fn slot_cipher(key_slot: &KeySlot, password: &str) -> Result<Aes256Gcm, AppError> {
+    let salt = hex::decode(&key_slot.salt)
+        .map_err(|_| AppError::Internal("Invalid key slot salt".to_string()))?;
+    let params = Params::new(key_slot.m_cost, key_slot.t_cost, key_slot.p_cost, Some(32))
+        .map_err(|e| AppError::Hashing(format!("Invalid key derivation params: {}", e)))?;
+
+    let mut key_bytes = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), &salt, &mut key_bytes)
+        .map_err(|e| AppError::Hashing(format!("Error hashing key: {}", e)))?;
+
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::file::is_password_protected;
+
+    const DATA_KEY: [u8; 32] = [1; 32];
+
+    fn is_unauthorized<T>(result: Result<T, AppError>) -> bool {
+        matches!(result, Err(AppError::Unauthorized(_)))
+    }
+
+    #[test]
+    fn unwraps_the_data_key_with_its_password() {
+        let key_slot = wrap_data_key(&DATA_KEY, "12345").unwrap();
+        let key_slots = [key_slot];
+
+        assert_eq!(unwrap_data_key(&key_slots, "12345").unwrap(), (0, DATA_KEY));
+        assert!(is_unauthorized(unwrap_data_key(&key_slots, "54321")));
+        assert!(is_unauthorized(unwrap_data_key(&[], "12345")));
+    }
+
+    #[test]
+    fn wraps_files_without_a_password_with_a_share_key() {
+        let (key_slot, share_key) = wrap_new_data_key(&DATA_KEY, None).unwrap();
+        let share_key = share_key.unwrap();
+        let key_slots = [key_slot];
+
+        assert!(!is_password_protected(&key_slots));
+        assert_eq!(
+            unwrap_data_key(&key_slots, &share_key).unwrap(),
+            (0, DATA_KEY)
+        );
+
+        let (key_slot, share_key) = wrap_new_data_key(&DATA_KEY, Some("12345")).unwrap();
+        assert!(share_key.is_none());
+        assert!(is_password_protected(&[key_slot]));
+    }
+
+    #[test]
+    fn adds_changes_and_removes_key_slots() {
+        let mut key_slots = vec![wrap_data_key(&DATA_KEY, "owner").unwrap()];
+
+        // Adding a password, as `add_file_password` does
+        let (_, data_key) = unwrap_data_key(&key_slots, "owner").unwrap();
+        key_slots.push(wrap_data_key(&data_key, "for-bob").unwrap());
+        assert_eq!(unwrap_data_key(&key_slots, "owner").unwrap(), (0, DATA_KEY));
+        assert_eq!(
+            unwrap_data_key(&key_slots, "for-bob").unwrap(),
+            (1, DATA_KEY)
+        );
+
+        // Changing one, as `change_file_password` does
+        let (index, data_key) = unwrap_data_key(&key_slots, "for-bob").unwrap();
+        key_slots[index] = wrap_data_key(&data_key, "for-carol").unwrap();
+        assert!(is_unauthorized(unwrap_data_key(&key_slots, "for-bob")));
+        assert_eq!(
+            unwrap_data_key(&key_slots, "for-carol").unwrap(),
+            (1, DATA_KEY)
+        );
+
+        // Removing one, as `remove_file_password` does
+        let (index, _) = unwrap_data_key(&key_slots, "owner").unwrap();
+        key_slots.remove(index);
+        assert!(is_unauthorized(unwrap_data_key(&key_slots, "owner")));
+        assert_eq!(
+            unwrap_data_key(&key_slots, "for-carol").unwrap(),
+            (0, DATA_KEY)
+        );
+    }
+
+    #[test]
+    fn opens_a_key_sealed_with_its_ticket_only() {
+        let sealed_key = seal_with_ticket("ticket", &DATA_KEY).unwrap();
+
+        assert_eq!(open_with_ticket("ticket", &sealed_key).unwrap(), DATA_KEY);
+        assert!(open_with_ticket("other ticket", &sealed_key).is_err());
+    }
+
+    #[test]
+    fn mixes_the_shred_key_into_the_content_key() {
+        assert_eq!(content_key(&DATA_KEY, None), DATA_KEY);
+        assert_ne!(content_key(&DATA_KEY, Some(&[2; 32])), DATA_KEY);
+        assert_ne!(
+            content_key(&DATA_KEY, Some(&[2; 32])),
+            content_key(&DATA_KEY, Some(&[3; 32]))
+        );
+    }
+}
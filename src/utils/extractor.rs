@@ -1,10 +1,12 @@
 use axum::{extract::FromRequestParts, http::request::Parts};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use mongodb::bson::oid::ObjectId;
 use reqwest::header;
 use serde::Deserialize;
+use subtle::ConstantTimeEq;
 
-use super::jwt::decode_jwt;
-use crate::error::AppError;
+use super::{hashing::hash_token, jwt::decode_jwt};
+use crate::{config::AppState, error::AppError};
 
 #[derive(Debug, Deserialize)]
 pub struct ExtractAuthAgent {
@@ -44,3 +46,147 @@ where
         })
     }
 }
+
+#[derive(Debug, Default)]
+pub struct ExtractBasicAuth {
+    pub password: Option<String>,
+}
+
+// ExtractBasicAuth reads a file password from an optional `Authorization: Basic` header,
+// so it doesn't have to be sent in the URL. The user name is ignored; requests without
+// the header get no password, and any other scheme is rejected.
+impl<S> FromRequestParts<S> for ExtractBasicAuth
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(auth_header) = parts.headers.get(header::AUTHORIZATION) else {
+            return Ok(ExtractBasicAuth::default());
+        };
+
+        let invalid = || AppError::BadRequest("Invalid Authorization header format".to_string());
+
+        let credentials = auth_header
+            .to_str()
+            .map_err(|_| invalid())?
+            .strip_prefix("Basic ")
+            .ok_or_else(|| AppError::Unauthorized("Expected Basic credentials".to_string()))?;
+
+        let credentials = STANDARD
+            .decode(credentials.trim())
+            .ok()
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+            .ok_or_else(invalid)?;
+
+        // `user:password`
+        let (_, password) = credentials.split_once(':').ok_or_else(invalid)?;
+
+        Ok(ExtractBasicAuth {
+            password: Some(password.to_string()),
+        })
+    }
+}
+
+/// Name of the header the admin API key is sent in.
+const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+#[derive(Debug)]
+pub struct ExtractAdmin;
+
+// ExtractAdmin guards the admin API: it checks the `X-Admin-Key` header against the
+// `ADMIN_API_KEY` loaded into `AppState`. Without a configured key every request is rejected.
+impl<S> FromRequestParts<S> for ExtractAdmin
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let admin_key_hash = parts
+            .extensions
+            .get::<AppState>()
+            .ok_or_else(|| AppError::Internal("Missing application state".to_string()))?
+            .admin_key_hash
+            .clone()
+            .ok_or_else(|| AppError::NotFound("The admin API is disabled".to_string()))?;
+
+        let key = parts
+            .headers
+            .get(ADMIN_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .ok_or_else(|| AppError::Unauthorized("Missing X-Admin-Key header".to_string()))?;
+
+        // Compared in constant time, and as hashes so the length of the key doesn't leak
+        let is_valid: bool = hash_token(key)
+            .as_bytes()
+            .ct_eq(admin_key_hash.as_bytes())
+            .into();
+        if !is_valid {
+            return Err(AppError::Unauthorized("Invalid admin key".to_string()));
+        }
+
+        Ok(ExtractAdmin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+
+    use super::*;
+
+    async fn basic_auth(authorization: Option<&str>) -> Result<Option<String>, AppError> {
+        let mut request = Request::builder();
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+
+        Ok(ExtractBasicAuth::from_request_parts(&mut parts, &())
+            .await?
+            .password)
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[tokio::test]
+    async fn reads_the_password_of_basic_credentials() {
+        assert_eq!(
+            basic_auth(Some(&basic("user:12345"))).await.unwrap(),
+            Some("12345".to_string())
+        );
+        // Only the first colon separates the user name
+        assert_eq!(
+            basic_auth(Some(&basic(":pass:word"))).await.unwrap(),
+            Some("pass:word".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_no_password_without_the_header() {
+        assert_eq!(basic_auth(None).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_credentials() {
+        for authorization in ["Basic not base64!", "Basic ", &basic("no colon")] {
+            assert!(
+                matches!(
+                    basic_auth(Some(authorization)).await,
+                    Err(AppError::BadRequest(_))
+                ),
+                "{}",
+                authorization
+            );
+        }
+
+        assert!(matches!(
+            basic_auth(Some("Bearer token")).await,
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+}
@@ -1,16 +1,280 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
 use aes_gcm::{
-    aead::{rand_core::RngCore, Aead},
+    aead::{
+        rand_core::RngCore,
+        stream::{NewStream, StreamBE32, StreamPrimitive},
+        Aead, Payload,
+    },
     Aes256Gcm, Key, KeyInit, Nonce,
 };
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
+use axum::body::Bytes;
+use futures::{stream, StreamExt, TryStreamExt};
+
+use crate::{
+    error::AppError,
+    storage::{BlobStream, StorageBackend},
+};
+
+/// Magic bytes every versioned (v2+) encrypted file starts with.
+const MAGIC: &[u8; 4] = b"FSRS";
+
+/// Current version of the encrypted file format.
+const FORMAT_VERSION: u8 = 2;
+
+/// Value of the header's KDF field for files encrypted with a random data key,
+/// which is kept (wrapped) outside of the file, see [`crate::utils::envelope`].
+const KDF_NONE: u8 = 0;
+
+/// Identifier of Argon2id in the header's KDF field, for files whose key is
+/// derived straight from the password.
+const KDF_ARGON2ID: u8 = 1;
+
+/// Size of the random salt used for key derivation.
+const SALT_SIZE: usize = 16;
+
+/// Size of the random nonce prefix; STREAM fills the remaining 5 bytes of the
+/// 12-byte AES-GCM nonce with the chunk counter and the last-chunk flag.
+const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Size of the AES-GCM authentication tag appended to every chunk.
+const TAG_SIZE: usize = 16;
+
+/// Plaintext size of every chunk (but the last) of a v2 file.
+pub const CHUNK_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// Size of a serialized v2 header:
+/// magic + version + kdf + m_cost + t_cost + p_cost + salt_len + salt + chunk_size + nonce_prefix.
+const HEADER_SIZE: usize = 4 + 1 + 1 + 4 + 4 + 4 + 1 + SALT_SIZE + 4 + NONCE_PREFIX_SIZE;
+
+/// Header of a v2 encrypted file.
+///
+/// It records everything needed to derive the key and walk the chunks, and is
+/// authenticated as associated data of every chunk.
+pub struct FileHeader {
+    kdf: u8,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: [u8; SALT_SIZE],
+    chunk_size: u32,
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+}
+
+impl FileHeader {
+    /// Creates a header with a fresh nonce prefix for a file encrypted with a data key.
+    ///
+    /// The key derivation fields are left zeroed, as the key does not come from a password.
+    pub fn generate() -> Self {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut nonce_prefix);
+
+        FileHeader {
+            kdf: KDF_NONE,
+            m_cost: 0,
+            t_cost: 0,
+            p_cost: 0,
+            salt: [0u8; SALT_SIZE],
+            chunk_size: CHUNK_SIZE as u32,
+            nonce_prefix,
+        }
+    }
 
-use crate::error::AppError;
+    /// Serializes the header; all integers are big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.kdf);
+        bytes.extend_from_slice(&self.m_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.t_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.p_cost.to_be_bytes());
+        bytes.push(SALT_SIZE as u8);
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.chunk_size.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce_prefix);
+        bytes
+    }
+
+    /// Parses the header at the start of an encrypted file.
+    ///
+    /// # Arguments
+    /// * `data` - The first bytes of the encrypted file (at least `HEADER_SIZE` for v2 files).
+    ///
+    /// # Returns
+    /// * `Ok(Some(FileHeader))` for a v2 file.
+    /// * `Ok(None)` if the file has no magic bytes, i.e. it is a legacy v1 blob.
+    /// * `Err(AppError)` if the header is truncated or uses an unknown version or KDF.
+    pub fn parse(data: &[u8]) -> Result<Option<Self>, AppError> {
+        if !data.starts_with(MAGIC) {
+            return Ok(None);
+        }
+
+        let header = data
+            .get(..HEADER_SIZE)
+            .ok_or_else(|| AppError::Internal("Truncated file header".to_string()))?;
+
+        if header[4] != FORMAT_VERSION {
+            return Err(AppError::Internal(format!(
+                "Unsupported file format version: {}",
+                header[4]
+            )));
+        }
+        if ![KDF_NONE, KDF_ARGON2ID].contains(&header[5]) || header[18] as usize != SALT_SIZE {
+            return Err(AppError::Internal(
+                "Unsupported key derivation parameters".to_string(),
+            ));
+        }
+
+        let read_u32 = |at: usize| {
+            u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+
+        let mut salt = [0u8; SALT_SIZE];
+        salt.copy_from_slice(&header[19..19 + SALT_SIZE]);
+
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        nonce_prefix.copy_from_slice(&header[HEADER_SIZE - NONCE_PREFIX_SIZE..]);
+
+        let chunk_size = read_u32(19 + SALT_SIZE);
+        if chunk_size == 0 {
+            return Err(AppError::Internal("Invalid chunk size".to_string()));
+        }
+
+        Ok(Some(FileHeader {
+            kdf: header[5],
+            m_cost: read_u32(6),
+            t_cost: read_u32(10),
+            p_cost: read_u32(14),
+            salt,
+            chunk_size,
+            nonce_prefix,
+        }))
+    }
+
+    /// Size of the serialized header, i.e. the offset of the first chunk.
+    pub fn size(&self) -> usize {
+        HEADER_SIZE
+    }
+
+    /// Plaintext size of every chunk but the last.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size as usize
+    }
+
+    /// Ciphertext size of every chunk but the last.
+    pub fn sealed_chunk_size(&self) -> usize {
+        self.chunk_size as usize + TAG_SIZE
+    }
+
+    /// Derives the file key from a password with the Argon2id parameters in this header.
+    ///
+    /// # Arguments
+    /// * `password` - The password the file was encrypted with.
+    ///
+    /// # Returns
+    /// * `Err(AppError)` for files encrypted with a data key, which has to be unwrapped instead.
+    fn derive_key(&self, password: &str) -> Result<[u8; 32], AppError> {
+        if self.kdf != KDF_ARGON2ID {
+            return Err(AppError::Internal(
+                "File key is not derived from a password".to_string(),
+            ));
+        }
+
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| AppError::Hashing(format!("Invalid key derivation params: {}", e)))?;
+
+        let mut key_bytes = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &self.salt, &mut key_bytes)
+            .map_err(|e| AppError::Hashing(format!("Error hashing key: {}", e)))?;
+
+        Ok(key_bytes)
+    }
+
+    /// Sets up the STREAM cipher for the key of this file.
+    ///
+    /// # Arguments
+    /// * `file_key` - The unwrapped data key, or the password of older files.
+    pub fn cipher(&self, file_key: &FileKey) -> Result<FileCipher, AppError> {
+        match file_key {
+            FileKey::DataKey(key_bytes) => Ok(self.cipher_with_key(key_bytes)),
+            FileKey::Password(password) => Ok(self.cipher_with_key(&self.derive_key(password)?)),
+        }
+    }
+
+    /// Sets up the STREAM cipher for a data key.
+    pub fn cipher_with_key(&self, key_bytes: &[u8; 32]) -> FileCipher {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key_bytes));
+
+        FileCipher {
+            stream: StreamBE32::from_aead(cipher, self.nonce_prefix.as_ref().into()),
+            aad: self.to_bytes(),
+        }
+    }
+}
+
+/// What a stored file is decrypted with.
+pub enum FileKey<'a> {
+    /// The data key of the file, unwrapped from one of its key slots.
+    DataKey([u8; 32]),
+    /// The password of a file stored before data keys existed.
+    Password(&'a str),
+}
+
+/// The STREAM cipher of a v2 file: seals and opens chunks at a given position.
+pub struct FileCipher {
+    stream: StreamBE32<Aes256Gcm>,
+    aad: Vec<u8>,
+}
+
+impl FileCipher {
+    /// Encrypts the chunk at `position`.
+    fn seal(&self, position: u32, is_last: bool, chunk: &[u8]) -> Result<Vec<u8>, AppError> {
+        self.stream
+            .encrypt(
+                position,
+                is_last,
+                Payload {
+                    msg: chunk,
+                    aad: &self.aad,
+                },
+            )
+            .map_err(|e| AppError::Internal(format!("Error in encrypting file: {}", e)))
+    }
+
+    /// Decrypts and authenticates the chunk at `position`.
+    ///
+    /// Fails if the chunk was tampered with, moved, or if `is_last` doesn't match
+    /// how it was sealed, so truncated files are detected.
+    pub fn open(&self, position: u32, is_last: bool, chunk: &[u8]) -> Result<Vec<u8>, AppError> {
+        self.stream
+            .decrypt(
+                position,
+                is_last,
+                Payload {
+                    msg: chunk,
+                    aad: &self.aad,
+                },
+            )
+            .map_err(|e| AppError::Internal(format!("Can not decrypt file: {}", e)))
+    }
+}
 
 /// Derives a 256-bit (32-byte) AES key from a user-provided password and a given salt using Argon2id.
 ///
+/// Only used for legacy v1 files; v2 files record their own parameters, see [`FileHeader::cipher`].
+///
 /// # Arguments
 /// * `password` - The user's password from which the key will be derived.
 /// * `salt` - A 16-byte random salt for key derivation.
@@ -27,7 +291,7 @@ pub fn derive_key_from_password(password: &str, salt: &[u8]) -> Result<[u8; 32],
     // Hash the password using Argon2id and the given salt
     let hash = argon2
         .hash_password(password.as_bytes(), &salt_string)
-        .map_err(|e| AppError::Hashing(format!("Error hashing key: {}", e.to_string())))?
+        .map_err(|e| AppError::Hashing(format!("Error hashing key: {}", e)))?
         .hash
         .ok_or("missing hash")
         .map_err(|e| AppError::Hashing(e.to_string()))?;
@@ -40,55 +304,307 @@ pub fn derive_key_from_password(password: &str, salt: &[u8]) -> Result<[u8; 32],
     Ok(key)
 }
 
-/// Encrypts data using AES-256-GCM with a password-derived key. Output format: salt + nonce + ciphertext.
+/// State of `encrypt_stream` between two chunks.
+struct EncryptState<'a> {
+    input: BlobStream<'a>,
+    buffer: Vec<u8>,
+    header: Option<Vec<u8>>,
+    cipher: FileCipher,
+    position: u32,
+    is_done: bool,
+}
+
+/// Encrypts a streamed file in the v2 format with a data key.
+/// Output format: header + sealed chunks.
+///
+/// The plaintext is cut into `CHUNK_SIZE` chunks, each sealed with AES-256-GCM under
+/// a STREAM nonce (prefix + chunk counter + last-chunk flag). Chunks are produced as
+/// the input arrives, so at most about one chunk is held in memory.
 ///
 /// # Arguments
-/// * `input_data_as_bytes` - The plaintext data to encrypt.
-/// * `password` - The password used to derive the encryption key.
+/// * `plaintext` - The plaintext file to encrypt.
+/// * `data_key` - The random key of the file, see [`crate::utils::envelope::generate_data_key`].
 ///
 /// # Returns
-/// * `Ok(Vec<u8>)` containing the encrypted data.
-/// * `Err(AppError)` if encryption or key derivation fails.
-pub fn encrypt_file_with_password(
-    input_data_as_bytes: Vec<u8>,
-    password: &str,
-) -> Result<Vec<u8>, AppError> {
-    // Generate a 16-byte random salt (used in key derivation)
-    let mut salt = [0u8; 16];
-    OsRng
-        .try_fill_bytes(&mut salt)
-        .map_err(|_| AppError::Internal("Error in generating 128-bit random salt".to_string()))?;
+/// * `BlobStream` yielding the encrypted data; read and encryption errors surface in the stream.
+pub fn encrypt_stream<'a>(plaintext: BlobStream<'a>, data_key: &[u8; 32]) -> BlobStream<'a> {
+    let header = FileHeader::generate();
+    let cipher = header.cipher_with_key(data_key);
 
-    // Generate a 12-byte nonce (required for AES-GCM)
-    let mut nonce_bytes = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce_bytes);
+    let state = EncryptState {
+        input: plaintext,
+        buffer: Vec::with_capacity(CHUNK_SIZE),
+        header: Some(header.to_bytes()),
+        cipher,
+        position: 0,
+        is_done: false,
+    };
 
-    // Derive a 32-byte key from the password + salt
-    let key_bytes = derive_key_from_password(password, &salt)?;
-    let key = Key::<Aes256Gcm>::from_slice(&key_bytes); // Wrap key bytes for AES-GCM usage
+    stream::try_unfold(state, |mut state| async move {
+        if let Some(header) = state.header.take() {
+            return Ok(Some((Bytes::from(header), state)));
+        }
 
-    // Create AES-256-GCM cipher instance
-    let cipher = Aes256Gcm::new(key);
-    let nonce = Nonce::from_slice(&nonce_bytes); // Wrap nonce bytes
+        if state.is_done {
+            return Ok(None);
+        }
 
-    // Encrypt the data using AES-GCM (authenticated encryption)
-    let ciphertext = cipher
-        .encrypt(nonce, input_data_as_bytes.as_ref())
-        .map_err(|e| AppError::Internal(format!("Error in encrypting file: {}", e)))?;
+        loop {
+            // A chunk is only known not to be the last once more data follows it
+            if state.buffer.len() > CHUNK_SIZE {
+                let rest = state.buffer.split_off(CHUNK_SIZE);
+                let chunk = std::mem::replace(&mut state.buffer, rest);
+                let sealed = state.cipher.seal(state.position, false, &chunk)?;
+                state.position = state.position.checked_add(1).ok_or_else(|| {
+                    AppError::BadRequest("File is too large to encrypt".to_string())
+                })?;
+                return Ok(Some((Bytes::from(sealed), state)));
+            }
 
-    // Combine salt + nonce + ciphertext
-    let mut output = Vec::new();
-    output.extend_from_slice(&salt);
-    output.extend_from_slice(&nonce_bytes);
-    output.extend_from_slice(&ciphertext);
+            match state.input.try_next().await? {
+                Some(chunk) => state.buffer.extend_from_slice(&chunk),
+                None => {
+                    let sealed = state.cipher.seal(state.position, true, &state.buffer)?;
+                    state.is_done = true;
+                    return Ok(Some((Bytes::from(sealed), state)));
+                }
+            }
+        }
+    })
+    .boxed()
+}
 
-    Ok(output)
+/// State of `encrypt_part` between two chunks.
+struct PartState<'a> {
+    input: BlobStream<'a>,
+    buffer: Vec<u8>,
+    cipher: &'a FileCipher,
+    position: u32,
+    remaining: u64,
+    sealed: &'a AtomicU64,
+    is_done: bool,
 }
 
-/// Decrypts data that was encrypted with `encrypt_file_with_password`.
+/// Encrypts one part of a file that is uploaded in several requests.
+///
+/// The part must start on a chunk boundary (`offset` is a multiple of `CHUNK_SIZE`).
+/// Only whole chunks are sealed; a trailing partial chunk is dropped unless it
+/// completes the file, so no plaintext has to be kept between requests and the
+/// client simply resends it with the next part.
 ///
 /// # Arguments
-/// * `encrypted_data` - The encrypted byte array containing salt + nonce + ciphertext.
+/// * `plaintext` - The bytes of this part.
+/// * `cipher` - The cipher of the file being uploaded.
+/// * `offset` - Where in the file this part starts.
+/// * `length` - The total size of the file.
+/// * `sealed` - Counter receiving the number of plaintext bytes that were sealed.
+///
+/// # Returns
+/// * `BlobStream` yielding the sealed chunks; it fails if the part runs past `length`.
+pub fn encrypt_part<'a>(
+    plaintext: BlobStream<'a>,
+    cipher: &'a FileCipher,
+    offset: u64,
+    length: u64,
+    sealed: &'a AtomicU64,
+) -> BlobStream<'a> {
+    let state = PartState {
+        input: plaintext,
+        buffer: Vec::with_capacity(CHUNK_SIZE),
+        cipher,
+        position: (offset / CHUNK_SIZE as u64) as u32,
+        remaining: length.saturating_sub(offset),
+        sealed,
+        is_done: false,
+    };
+
+    stream::try_unfold(state, |mut state| async move {
+        if state.is_done {
+            return Ok(None);
+        }
+
+        loop {
+            if state.remaining > CHUNK_SIZE as u64 && state.buffer.len() >= CHUNK_SIZE {
+                let rest = state.buffer.split_off(CHUNK_SIZE);
+                let chunk = std::mem::replace(&mut state.buffer, rest);
+                let sealed = state.cipher.seal(state.position, false, &chunk)?;
+
+                state.position += 1;
+                state.remaining -= CHUNK_SIZE as u64;
+                state.sealed.fetch_add(CHUNK_SIZE as u64, Ordering::Relaxed);
+                return Ok(Some((Bytes::from(sealed), state)));
+            }
+
+            if state.remaining <= CHUNK_SIZE as u64 && state.buffer.len() as u64 >= state.remaining
+            {
+                // The final chunk; nothing may follow it
+                while let Some(chunk) = state.input.try_next().await? {
+                    state.buffer.extend_from_slice(&chunk);
+                }
+                if state.buffer.len() as u64 > state.remaining {
+                    return Err(AppError::BadRequest(
+                        "Upload is larger than its declared length".to_string(),
+                    ));
+                }
+
+                let sealed = state.cipher.seal(state.position, true, &state.buffer)?;
+                state
+                    .sealed
+                    .fetch_add(state.buffer.len() as u64, Ordering::Relaxed);
+                state.is_done = true;
+                return Ok(Some((Bytes::from(sealed), state)));
+            }
+
+            match state.input.try_next().await? {
+                Some(chunk) => state.buffer.extend_from_slice(&chunk),
+                // Drop the trailing partial chunk
+                None => return Ok(None),
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Number of chunks fetched from storage per request while decrypting a range.
+const CHUNKS_PER_FETCH: u64 = 16; // 1 MiB of plaintext
+
+/// State of `decrypt_range` between two batches of chunks.
+struct DecryptState {
+    storage: Arc<dyn StorageBackend>,
+    cid: String,
+    header: FileHeader,
+    cipher: FileCipher,
+    next_chunk: u64,
+    last_needed_chunk: u64,
+    last_chunk: u64,
+    range: Range<u64>,
+}
+
+impl DecryptState {
+    /// Fetches and decrypts the next batch of chunks, trimmed to the requested range.
+    ///
+    /// # Returns
+    /// * `Ok(Some(Bytes))` with the plaintext of the batch.
+    /// * `Ok(None)` once every chunk covering the range was served.
+    async fn next_batch(&mut self) -> Result<Option<Bytes>, AppError> {
+        if self.next_chunk > self.last_needed_chunk {
+            return Ok(None);
+        }
+
+        let first = self.next_chunk;
+        let last = (first + CHUNKS_PER_FETCH - 1).min(self.last_needed_chunk);
+        let sealed_chunk_size = self.header.sealed_chunk_size() as u64;
+        let chunk_size = self.header.chunk_size() as u64;
+
+        let sealed = self
+            .storage
+            .get_range(
+                &self.cid,
+                self.header.size() as u64 + first * sealed_chunk_size,
+                (last - first + 1) * sealed_chunk_size,
+            )
+            .await?;
+
+        // Every requested chunk has to be there, the last one at least as its tag
+        if sealed.len() as u64 <= (last - first) * sealed_chunk_size {
+            return Err(AppError::Internal(
+                "Encrypted file is truncated".to_string(),
+            ));
+        }
+
+        let mut plaintext = Vec::with_capacity(sealed.len());
+        for (i, chunk) in sealed.chunks(sealed_chunk_size as usize).enumerate() {
+            let position = first + i as u64;
+            let opened = self
+                .cipher
+                .open(position as u32, position == self.last_chunk, chunk)?;
+
+            // Trim the chunk to the part that overlaps the requested range
+            let chunk_start = position * chunk_size;
+            let from = self.range.start.saturating_sub(chunk_start) as usize;
+            let to = (self.range.end - chunk_start).min(opened.len() as u64) as usize;
+            if from < to {
+                plaintext.extend_from_slice(&opened[from..to]);
+            }
+        }
+
+        self.next_chunk = last + 1;
+        Ok(Some(Bytes::from(plaintext)))
+    }
+}
+
+/// Decrypts the plaintext bytes `range` of a stored file.
+///
+/// For v2 files only the chunks covering the range are fetched from storage, a batch
+/// at a time. The first batch is decrypted before returning, so a wrong password
+/// is reported before any response is sent. Legacy v1 blobs are fetched and
+/// decrypted whole.
+///
+/// # Arguments
+/// * `storage` - The storage backend holding the file.
+/// * `cid` - The identifier of the encrypted file in storage.
+/// * `size` - The plaintext size of the file.
+/// * `file_key` - The data key or (for older files) the password of the file.
+/// * `range` - The plaintext bytes to return; must lie within `0..size`.
+///
+/// # Returns
+/// * `Ok(BlobStream)` yielding the decrypted range.
+/// * `Err(AppError)` if fetching, key derivation or decrypting the first batch fails.
+pub async fn decrypt_range(
+    storage: Arc<dyn StorageBackend>,
+    cid: &str,
+    size: u64,
+    file_key: &FileKey<'_>,
+    range: Range<u64>,
+) -> Result<BlobStream<'static>, AppError> {
+    let header_bytes = storage.get_range(cid, 0, HEADER_SIZE as u64).await?;
+
+    let Some(header) = FileHeader::parse(&header_bytes)? else {
+        let FileKey::Password(password) = file_key else {
+            return Err(AppError::Internal(
+                "Legacy files can only be decrypted with a password".to_string(),
+            ));
+        };
+
+        let encrypted_file = storage.get(cid).await?;
+        let plaintext = decrypt_file_with_password(&encrypted_file, password)?;
+        let plaintext = Bytes::from(plaintext).slice(range.start as usize..range.end as usize);
+        return Ok(stream::once(async { Ok(plaintext) }).boxed());
+    };
+
+    let chunk_size = header.chunk_size() as u64;
+
+    // An empty file still has one (empty) sealed chunk
+    let last_chunk = size.div_ceil(chunk_size).max(1) - 1;
+    let first_chunk = (range.start / chunk_size).min(last_chunk);
+    let last_needed_chunk =
+        (range.end.saturating_sub(1) / chunk_size).clamp(first_chunk, last_chunk);
+
+    let mut state = DecryptState {
+        storage,
+        cid: cid.to_string(),
+        cipher: header.cipher(file_key)?,
+        header,
+        next_chunk: first_chunk,
+        last_needed_chunk,
+        last_chunk,
+        range,
+    };
+
+    let first_batch = state.next_batch().await?;
+
+    let rest = stream::try_unfold(state, |mut state| async move {
+        Ok(state.next_batch().await?.map(|batch| (batch, state)))
+    });
+
+    Ok(stream::iter(first_batch.map(Ok)).chain(rest).boxed())
+}
+
+/// Decrypts data that was encrypted with `encrypt_stream` (v2) or by the legacy
+/// single-blob format (v1: salt + nonce + ciphertext).
+///
+/// # Arguments
+/// * `encrypted_data` - The encrypted byte array.
 /// * `password` - The password used to derive the decryption key.
 ///
 /// # Returns
@@ -97,6 +613,33 @@ pub fn encrypt_file_with_password(
 pub fn decrypt_file_with_password(
     encrypted_data: &[u8],
     password: &str,
+) -> Result<Vec<u8>, AppError> {
+    let Some(header) = FileHeader::parse(encrypted_data)? else {
+        return decrypt_legacy_file_with_password(encrypted_data, password);
+    };
+
+    let sealed_chunks = encrypted_data[header.size()..].chunks(header.sealed_chunk_size());
+
+    // An empty file still has one (empty) sealed chunk
+    if sealed_chunks.len() == 0 {
+        return Err(AppError::Internal("Missing ciphertext".to_string()));
+    }
+
+    let cipher = header.cipher(&FileKey::Password(password))?;
+    let last = sealed_chunks.len() - 1;
+
+    let mut plaintext = Vec::with_capacity(encrypted_data.len());
+    for (position, chunk) in sealed_chunks.enumerate() {
+        plaintext.extend(cipher.open(position as u32, position == last, chunk)?);
+    }
+
+    Ok(plaintext)
+}
+
+/// Decrypts a legacy v1 blob: one AES-256-GCM ciphertext as salt + nonce + ciphertext.
+fn decrypt_legacy_file_with_password(
+    encrypted_data: &[u8],
+    password: &str,
 ) -> Result<Vec<u8>, AppError> {
     // Extract salt (first 16 bytes)
     let salt = encrypted_data
@@ -126,3 +669,271 @@ pub fn decrypt_file_with_password(
 
     Ok(plaintext)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::storage::local::LocalStorage;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    /// A local storage directory removed again when the test ends.
+    struct TestStorage {
+        root: PathBuf,
+        storage: Arc<dyn StorageBackend>,
+    }
+
+    impl TestStorage {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("fileshare-rs-{}", uuid::Uuid::new_v4()));
+            TestStorage {
+                storage: Arc::new(LocalStorage::new(root.clone())),
+                root,
+            }
+        }
+
+        async fn put(&self, blob: Vec<u8>) -> String {
+            self.storage
+                .put("test", stream::iter([Ok(Bytes::from(blob))]).boxed())
+                .await
+                .unwrap()
+        }
+
+        /// Encrypts `plaintext`, fed in pieces that don't line up with chunks.
+        async fn encrypt(&self, plaintext: &[u8]) -> String {
+            let pieces = plaintext
+                .chunks(10_000)
+                .map(|piece| Ok(Bytes::copy_from_slice(piece)))
+                .collect::<Vec<_>>();
+            let encrypted = encrypt_stream(stream::iter(pieces).boxed(), &KEY);
+
+            self.storage.put("test", encrypted).await.unwrap()
+        }
+
+        async fn decrypt(
+            &self,
+            cid: &str,
+            size: u64,
+            range: Range<u64>,
+        ) -> Result<Vec<u8>, AppError> {
+            let plaintext = decrypt_range(
+                self.storage.clone(),
+                cid,
+                size,
+                &FileKey::DataKey(KEY),
+                range,
+            )
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+            Ok(plaintext.concat())
+        }
+    }
+
+    impl Drop for TestStorage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn plaintext(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn round_trips_files_of_any_size() {
+        let storage = TestStorage::new();
+
+        // Empty, partial last chunks, and last chunks of exactly `CHUNK_SIZE`
+        for size in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            3 * CHUNK_SIZE,
+        ] {
+            let plaintext = plaintext(size);
+            let cid = storage.encrypt(&plaintext).await;
+
+            let decrypted = storage.decrypt(&cid, size as u64, 0..size as u64).await;
+            assert_eq!(decrypted.unwrap(), plaintext, "size {}", size);
+        }
+    }
+
+    #[tokio::test]
+    async fn decrypts_ranges_across_chunk_boundaries() {
+        let storage = TestStorage::new();
+        // Spans more than one batch of chunks fetched from storage
+        let size = (CHUNKS_PER_FETCH as usize + 2) * CHUNK_SIZE + 123;
+        let plaintext = plaintext(size);
+        let cid = storage.encrypt(&plaintext).await;
+
+        let boundary = CHUNK_SIZE as u64;
+        let batch_boundary = CHUNKS_PER_FETCH * CHUNK_SIZE as u64;
+        for range in [
+            boundary - 10..boundary + 10,
+            boundary..boundary + 1,
+            boundary - 1..boundary,
+            batch_boundary - 5..batch_boundary + 5,
+            1..size as u64 - 1,
+            size as u64 - 200..size as u64,
+        ] {
+            let decrypted = storage.decrypt(&cid, size as u64, range.clone()).await;
+            assert_eq!(
+                decrypted.unwrap(),
+                &plaintext[range.start as usize..range.end as usize],
+                "range {:?}",
+                range
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_parts_into_the_same_format() {
+        let storage = TestStorage::new();
+        let size = 2 * CHUNK_SIZE;
+        let plaintext = plaintext(size);
+
+        let header = FileHeader::generate();
+        let cipher = header.cipher_with_key(&KEY);
+        let mut blob = header.to_bytes();
+
+        // A trailing partial chunk is dropped, and sent again with the next part
+        let mut offset = 0;
+        for end in [CHUNK_SIZE + 100, size] {
+            let sealed = AtomicU64::new(0);
+            let part = stream::iter([Ok(Bytes::copy_from_slice(&plaintext[offset..end]))]).boxed();
+            let encrypted = encrypt_part(part, &cipher, offset as u64, size as u64, &sealed)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+                .concat();
+
+            blob.extend_from_slice(&encrypted);
+            offset += sealed.into_inner() as usize;
+        }
+        assert_eq!(offset, size);
+
+        let cid = storage.put(blob).await;
+        let decrypted = storage.decrypt(&cid, size as u64, 0..size as u64).await;
+        assert_eq!(decrypted.unwrap(), plaintext);
+    }
+
+    #[tokio::test]
+    async fn rejects_parts_past_the_declared_length() {
+        let header = FileHeader::generate();
+        let cipher = header.cipher_with_key(&KEY);
+        let sealed = AtomicU64::new(0);
+
+        let part = stream::iter([Ok(Bytes::from(plaintext(101)))]).boxed();
+        let encrypted = encrypt_part(part, &cipher, 0, 100, &sealed)
+            .try_collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(encrypted, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn detects_tampered_chunks() {
+        let storage = TestStorage::new();
+        let size = 3 * CHUNK_SIZE;
+        let plaintext = plaintext(size);
+        let cid = storage.encrypt(&plaintext).await;
+        let blob = storage.storage.get(&cid).await.unwrap();
+
+        // Flip a bit in the second chunk
+        let mut tampered = blob.clone();
+        tampered[HEADER_SIZE + CHUNK_SIZE + TAG_SIZE + 10] ^= 1;
+        let tampered = storage.put(tampered).await;
+
+        let second_chunk = CHUNK_SIZE as u64..2 * CHUNK_SIZE as u64;
+        assert!(storage
+            .decrypt(&tampered, size as u64, second_chunk)
+            .await
+            .is_err());
+        assert!(storage
+            .decrypt(&tampered, size as u64, 0..size as u64)
+            .await
+            .is_err());
+        // Chunks are authenticated one by one, so the first is still readable
+        let first_chunk = storage.decrypt(&tampered, size as u64, 0..10).await;
+        assert_eq!(first_chunk.unwrap(), &plaintext[..10]);
+
+        // Swapped chunks are detected as well
+        let sealed_chunk_size = CHUNK_SIZE + TAG_SIZE;
+        let mut swapped = blob.clone();
+        swapped[HEADER_SIZE..HEADER_SIZE + 2 * sealed_chunk_size].rotate_left(sealed_chunk_size);
+        let swapped = storage.put(swapped).await;
+        assert!(storage.decrypt(&swapped, size as u64, 0..10).await.is_err());
+
+        // So is a file cut off after a full chunk
+        let truncated = storage
+            .put(blob[..HEADER_SIZE + 2 * sealed_chunk_size].to_vec())
+            .await;
+        let decrypted = storage
+            .decrypt(&truncated, 2 * CHUNK_SIZE as u64, 0..2 * CHUNK_SIZE as u64)
+            .await;
+        assert!(decrypted.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_the_wrong_key() {
+        let storage = TestStorage::new();
+        let cid = storage.encrypt(&plaintext(100)).await;
+
+        let decrypted = decrypt_range(
+            storage.storage.clone(),
+            &cid,
+            100,
+            &FileKey::DataKey([8; 32]),
+            0..100,
+        )
+        .await;
+
+        assert!(decrypted.is_err());
+    }
+
+    /// "Hello from a v1 file!" as the single-blob format wrote it, with password `12345`.
+    const V1_BLOB: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1becb626b4\
+                           0b309571abf03799cf0a16b5eaed3c4029f414bd9340afc175b3fb656f2f2b292f";
+
+    #[tokio::test]
+    async fn decrypts_legacy_v1_blobs() {
+        let test = TestStorage::new();
+        let cid = test.put(hex::decode(V1_BLOB).unwrap()).await;
+        let plaintext = b"Hello from a v1 file!";
+        let size = plaintext.len() as u64;
+
+        let decrypt = |password: &'static str, range: Range<u64>| {
+            let storage = test.storage.clone();
+            let cid = cid.clone();
+            async move {
+                let plaintext =
+                    decrypt_range(storage, &cid, size, &FileKey::Password(password), range)
+                        .await?
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                Ok::<_, AppError>(plaintext.concat())
+            }
+        };
+
+        assert_eq!(decrypt("12345", 0..size).await.unwrap(), plaintext);
+        assert_eq!(decrypt("12345", 6..10).await.unwrap(), b"from");
+        assert!(decrypt("54321", 0..size).await.is_err());
+
+        // Legacy blobs only ever had a password
+        assert!(decrypt_range(
+            test.storage.clone(),
+            &cid,
+            size,
+            &FileKey::DataKey(KEY),
+            0..size
+        )
+        .await
+        .is_err());
+    }
+}
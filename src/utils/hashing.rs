@@ -3,6 +3,8 @@ use argon2::{
     Argon2, PasswordHash, PasswordVerifier,
 };
 
+use sha2::{Digest, Sha256};
+
 use crate::error::AppError;
 
 /// Hashes a given secret (e.g., a password or token) using the Argon2 algorithm.
@@ -37,9 +39,32 @@ pub fn hash_secret(secret: &str) -> Result<String, AppError> {
 pub fn verify_secret(hashed_secret: &str, given_value: &str) -> Result<bool, AppError> {
     let argon2 = Argon2::default();
     let parsed_hash =
-        PasswordHash::new(&hashed_secret).map_err(|e| AppError::Hashing(e.to_string()))?;
+        PasswordHash::new(hashed_secret).map_err(|e| AppError::Hashing(e.to_string()))?;
 
     Ok(argon2
         .verify_password(given_value.as_bytes(), &parsed_hash)
         .is_ok())
 }
+
+/// Hashes a random, high-entropy token (SHA-256, hex) so it can be looked up by its hash.
+///
+/// Unlike `hash_secret` this is deterministic, so it must never be used for passwords.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_tokens_to_the_same_hex_digest() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // So a token can be looked up by its hash
+        assert_eq!(hash_token("token"), hash_token("token"));
+        assert_ne!(hash_token("token"), hash_token("other token"));
+    }
+}
@@ -1,4 +1,5 @@
-use mongodb::bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, oid::ObjectId, Bson};
 
 use crate::error::AppError;
 
@@ -30,3 +31,67 @@ pub fn str_to_object_id(str: &str) -> Result<ObjectId, AppError> {
 
     Ok(object_id)
 }
+
+/// Serializes a datetime the way it is stored in `FileCollection`, for comparisons.
+///
+/// # Arguments
+/// * `date` - The datetime to compare stored dates with.
+///
+/// # Returns
+/// * `Ok(Bson)` with the datetime as stored (an RFC 3339 string).
+/// * `Err(AppError::Internal)` if it can't be serialized.
+pub fn date_to_bson(date: &DateTime<Utc>) -> Result<Bson, AppError> {
+    bson::to_bson(date).map_err(|_| AppError::Internal("Failed to serialize date".to_string()))
+}
+
+/// Escapes a string so it matches literally inside a MongoDB `$regex`.
+///
+/// # Arguments
+/// * `text` - The user-supplied text.
+///
+/// # Returns
+/// * `String` with every regex metacharacter escaped.
+pub fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}-/#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Builds an `attachment` `Content-Disposition` for a file name.
+///
+/// The name is sent both as an ASCII fallback (for old clients) and RFC 5987 encoded
+/// in `filename*`, so any name can be sent without breaking the header.
+///
+/// # Arguments
+/// * `name` - The name of the file, e.g. `Résumé "final".pdf`.
+///
+/// # Returns
+/// * `String` like `attachment; filename="R_sum_ _final_.pdf"; filename*=UTF-8''R%C3%A9sum%C3%A9...`.
+pub fn content_disposition(name: &str) -> String {
+    let fallback = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
@@ -1,8 +1,14 @@
-pub mod cloudinary;
 pub mod email;
+pub mod envelope;
 pub mod extractor;
 pub mod file;
 pub mod hashing;
 pub mod jwt;
 pub mod misc;
+pub mod pagination;
+pub mod range;
+pub mod share_code;
+pub mod signed_url;
 pub mod tracing;
+pub mod tus;
+pub mod zip;
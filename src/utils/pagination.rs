@@ -0,0 +1,89 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mongodb::bson::{self, doc, oid::ObjectId, Bson, Document};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+/// Default number of items in a page.
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+
+/// Direction of a sort.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn direction(self) -> i32 {
+        match self {
+            SortOrder::Asc => 1,
+            SortOrder::Desc => -1,
+        }
+    }
+}
+
+/// Position after the last item of a page: its sort value and `_id` (the tiebreaker).
+///
+/// Handed to clients as an opaque base64url string.
+pub struct Cursor {
+    value: Bson,
+    id: ObjectId,
+}
+
+impl Cursor {
+    /// Creates the cursor pointing after a document.
+    ///
+    /// # Arguments
+    /// * `document` - The last document of a page.
+    /// * `field` - The field the page is sorted by.
+    pub fn after(document: &Document, field: &str) -> Result<Self, AppError> {
+        Ok(Cursor {
+            value: document.get(field).cloned().unwrap_or(Bson::Null),
+            id: document
+                .get_object_id("_id")
+                .map_err(|_| AppError::Internal("Document without an id".to_string()))?,
+        })
+    }
+
+    pub fn encode(&self) -> Result<String, AppError> {
+        let bytes = bson::to_vec(&doc! {"v": self.value.clone(), "id": self.id})
+            .map_err(|_| AppError::Internal("Failed to serialize cursor".to_string()))?;
+
+        Ok(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let document = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+
+        Ok(Cursor {
+            value: document.get("v").cloned().ok_or_else(invalid)?,
+            id: document.get_object_id("id").map_err(|_| invalid())?,
+        })
+    }
+
+    /// Filter matching the documents that come after this cursor.
+    pub fn filter(&self, field: &str, order: SortOrder) -> Document {
+        let op = match order {
+            SortOrder::Asc => "$gt",
+            SortOrder::Desc => "$lt",
+        };
+
+        doc! {
+            "$or": [
+                { field: { op: self.value.clone() } },
+                { field: self.value.clone(), "_id": { op: self.id } },
+            ]
+        }
+    }
+}
+
+/// Sort document for `field`, with `_id` as tiebreaker so cursors are stable.
+pub fn sort_by(field: &str, order: SortOrder) -> Document {
+    doc! { field: order.direction(), "_id": order.direction() }
+}
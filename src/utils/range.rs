@@ -0,0 +1,140 @@
+use std::ops::Range;
+
+use axum::http::{header, HeaderMap};
+
+/// What part of a file a download request asks for.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// The whole file (no `Range` header, or one that has to be ignored).
+    Full,
+    /// A single satisfiable byte range.
+    Partial(Range<u64>),
+    /// A range that lies outside of the file.
+    Unsatisfiable,
+}
+
+/// Resolves the `Range` and `If-Range` headers of a request against a file.
+///
+/// Only single `bytes` ranges are supported; multiple ranges and unknown units are
+/// ignored and the whole file is served, as RFC 9110 allows. `If-Range` is only
+/// honored with the file's ETag; any other validator also results in the whole file.
+///
+/// # Arguments
+/// * `headers` - The request headers.
+/// * `etag` - The ETag of the file, including quotes.
+/// * `size` - The size of the file in bytes.
+///
+/// # Returns
+/// * `RangeRequest` describing what should be served.
+pub fn resolve_range(headers: &HeaderMap, etag: &str, size: u64) -> RangeRequest {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        if if_range.to_str().ok().map(str::trim) != Some(etag) {
+            return RangeRequest::Full;
+        }
+    }
+
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // `bytes=start-end`
+        (Ok(start), Ok(end)) if start <= end => start..end.saturating_add(1).min(size),
+        // `bytes=start-`
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        // `bytes=-suffix`
+        (Err(_), Ok(suffix)) if start.is_empty() => size.saturating_sub(suffix)..size,
+        _ => return RangeRequest::Full,
+    };
+
+    if range.start >= size || range.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Partial(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    const ETAG: &str = "\"6811a257200ffe8eb047b776\"";
+
+    fn resolve(range: &str, size: u64) -> RangeRequest {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_str(range).unwrap());
+        resolve_range(&headers, ETAG, size)
+    }
+
+    #[test]
+    fn serves_closed_ranges() {
+        assert_eq!(resolve("bytes=0-9", 100), RangeRequest::Partial(0..10));
+        assert_eq!(resolve("bytes=99-99", 100), RangeRequest::Partial(99..100));
+        // The end is clamped to the file
+        assert_eq!(resolve("bytes=90-500", 100), RangeRequest::Partial(90..100));
+    }
+
+    #[test]
+    fn serves_open_ended_ranges() {
+        assert_eq!(resolve("bytes=0-", 100), RangeRequest::Partial(0..100));
+        assert_eq!(resolve("bytes=40-", 100), RangeRequest::Partial(40..100));
+    }
+
+    #[test]
+    fn serves_suffix_ranges() {
+        assert_eq!(resolve("bytes=-10", 100), RangeRequest::Partial(90..100));
+        // A suffix longer than the file is the whole file
+        assert_eq!(resolve("bytes=-500", 100), RangeRequest::Partial(0..100));
+        assert_eq!(resolve("bytes=-0", 100), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn rejects_ranges_past_the_end() {
+        assert_eq!(resolve("bytes=100-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(resolve("bytes=100-200", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(resolve("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(resolve("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_unsupported_ranges() {
+        assert_eq!(resolve("bytes=20-10", 100), RangeRequest::Full);
+        assert_eq!(resolve("bytes=0-9,20-29", 100), RangeRequest::Full);
+        assert_eq!(resolve("items=0-9", 100), RangeRequest::Full);
+        assert_eq!(resolve("bytes=a-b", 100), RangeRequest::Full);
+        assert_eq!(resolve("bytes=-", 100), RangeRequest::Full);
+        assert_eq!(
+            resolve_range(&HeaderMap::new(), ETAG, 100),
+            RangeRequest::Full
+        );
+    }
+
+    #[test]
+    fn honors_if_range_only_with_the_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=10-"));
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_str(ETAG).unwrap());
+        assert_eq!(
+            resolve_range(&headers, ETAG, 100),
+            RangeRequest::Partial(10..100)
+        );
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        assert_eq!(resolve_range(&headers, ETAG, 100), RangeRequest::Full);
+    }
+}
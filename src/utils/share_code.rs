@@ -0,0 +1,119 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bip39::Mnemonic;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+
+use crate::error::AppError;
+
+/// Bytes of randomness in a share code.
+const SHARE_CODE_SIZE: usize = 16; // 128 bits
+
+/// Length of the short (base64url) form of a share code.
+const SHARE_CODE_LENGTH: usize = 22;
+
+/// Generates the random share code a file is shared by, in its short form.
+pub fn generate_share_code() -> String {
+    let mut share_code = [0u8; SHARE_CODE_SIZE];
+    OsRng.fill_bytes(&mut share_code);
+    URL_SAFE_NO_PAD.encode(share_code)
+}
+
+/// Spells a share code as words from the BIP39 English list, e.g. `correct-horse-battery-...`.
+///
+/// The words encode the same 128 bits (plus a checksum), so they are easier to read out
+/// but not easier to guess.
+///
+/// # Arguments
+/// * `share_code` - The short form of the share code.
+pub fn share_code_words(share_code: &str) -> Result<String, AppError> {
+    let invalid = || AppError::Internal("Invalid share code".to_string());
+
+    let entropy = URL_SAFE_NO_PAD.decode(share_code).map_err(|_| invalid())?;
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|_| invalid())?;
+
+    Ok(mnemonic.words().collect::<Vec<_>>().join("-"))
+}
+
+/// Filter matching the file a share code refers to.
+///
+/// Accepts the short form, the word form (separated by `-` or spaces) and, for files
+/// stored before share codes existed, the file's id.
+///
+/// # Arguments
+/// * `code` - The share code as given by a recipient.
+///
+/// # Returns
+/// * `Ok(Document)` to find the file with.
+/// * `Err(AppError::BadRequest)` if `code` is none of the above.
+pub fn share_code_filter(code: &str) -> Result<Document, AppError> {
+    let code = code.trim();
+
+    if code.len() == SHARE_CODE_LENGTH {
+        return Ok(doc! {"share_code": code});
+    }
+
+    // Files with a share code can't be enumerated by their id
+    if let Ok(file_id) = ObjectId::parse_str(code) {
+        return Ok(doc! {"_id": file_id, "share_code": null});
+    }
+
+    let words = code.to_lowercase().replace('-', " ");
+    let mnemonic = Mnemonic::parse_normalized(&words)
+        .map_err(|_| AppError::BadRequest("Invalid share code".to_string()))?;
+
+    Ok(doc! {"share_code": URL_SAFE_NO_PAD.encode(mnemonic.to_entropy())})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_file_by_its_short_code() {
+        let share_code = generate_share_code();
+        assert_eq!(share_code.len(), SHARE_CODE_LENGTH);
+
+        assert_eq!(
+            share_code_filter(&format!(" {} ", share_code)).unwrap(),
+            doc! {"share_code": &share_code}
+        );
+    }
+
+    #[test]
+    fn finds_a_file_by_its_words() {
+        let share_code = generate_share_code();
+        let words = share_code_words(&share_code).unwrap();
+        assert_eq!(words.split('-').count(), 12);
+
+        for words in [words.clone(), words.replace('-', " "), words.to_uppercase()] {
+            assert_eq!(
+                share_code_filter(&words).unwrap(),
+                doc! {"share_code": &share_code}
+            );
+        }
+    }
+
+    #[test]
+    fn finds_only_older_files_by_their_id() {
+        let file_id = ObjectId::new();
+
+        assert_eq!(
+            share_code_filter(&file_id.to_hex()).unwrap(),
+            doc! {"_id": file_id, "share_code": null}
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        // Twelve valid words, but the last one doesn't match the checksum
+        let wrong_checksum = ["abandon"; 12].join("-");
+
+        for code in ["", "abc", "correct-horse-battery-staple", &wrong_checksum] {
+            assert!(matches!(
+                share_code_filter(code),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+        assert!(share_code_words("not base64!").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use std::net::IpAddr;
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit, Nonce,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use mongodb::bson::oid::ObjectId;
+use sha2::Sha256;
+
+use crate::error::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Size of the AES-GCM nonce used to wrap the data key of a signed URL.
+const NONCE_SIZE: usize = 12;
+
+/// What a signed download URL grants, all covered by its signature.
+pub struct SignedUrlClaims {
+    pub file_id: ObjectId,
+    // Unix timestamp
+    pub expires: i64,
+    // Only this client may use the URL
+    pub ip: Option<IpAddr>,
+    // `signed_url_version` of the file when the URL was minted
+    pub version: u32,
+    // The data key of the file, wrapped by `wrap_url_key`
+    pub key: Option<String>,
+}
+
+impl SignedUrlClaims {
+    /// The signed part of the URL, one field per line.
+    fn message(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.file_id.to_hex(),
+            self.expires,
+            self.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            self.version,
+            self.key.as_deref().unwrap_or_default(),
+        )
+    }
+}
+
+/// The keys of signed URLs, derived from the JWT secret once at startup.
+#[derive(Clone)]
+pub struct SignedUrlKeys {
+    signing_key: [u8; 32],
+    wrapping_key: [u8; 32],
+}
+
+impl SignedUrlKeys {
+    pub fn new(jwt_secret_key: &str) -> Self {
+        SignedUrlKeys {
+            signing_key: derive_key(jwt_secret_key, "fileshare-rs signed download URL"),
+            wrapping_key: derive_key(jwt_secret_key, "fileshare-rs signed download URL key"),
+        }
+    }
+
+    fn url_mac(&self) -> HmacSha256 {
+        <HmacSha256 as Mac>::new_from_slice(&self.signing_key)
+            .expect("HMAC can take a key of any size")
+    }
+
+    fn url_key_cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.wrapping_key))
+    }
+}
+
+/// Derives a key for `purpose` from the JWT secret, so it never signs anything else.
+fn derive_key(jwt_secret_key: &str, purpose: &str) -> [u8; 32] {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(jwt_secret_key.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(purpose.as_bytes());
+
+    mac.finalize().into_bytes().into()
+}
+
+/// Signs the claims of a download URL.
+///
+/// # Returns
+/// * `String` with the base64url encoded HMAC-SHA256 signature.
+pub fn sign_download_url(keys: &SignedUrlKeys, claims: &SignedUrlClaims) -> String {
+    let mut mac = keys.url_mac();
+    mac.update(claims.message().as_bytes());
+
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// Checks a download URL: its signature (in constant time), then its expiry and the
+/// address it is bound to.
+///
+/// # Arguments
+/// * `claims` - The claims read from the URL.
+/// * `signature` - The `sig` of the URL.
+/// * `client_ip` - The address the URL is used from.
+/// * `now` - The current time.
+///
+/// # Returns
+/// * `Ok(())` if `signature` was made by `sign_download_url` for these claims, and they
+///   allow a download from `client_ip` at `now`.
+/// * `Err(AppError::Unauthorized)` otherwise.
+pub fn verify_download_url(
+    keys: &SignedUrlKeys,
+    claims: &SignedUrlClaims,
+    signature: &str,
+    client_ip: IpAddr,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let invalid = || AppError::Unauthorized("Invalid URL signature".to_string());
+
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+
+    let mut mac = keys.url_mac();
+    mac.update(claims.message().as_bytes());
+    mac.verify_slice(&signature).map_err(|_| invalid())?;
+
+    if claims.expires <= now.timestamp() {
+        return Err(AppError::Unauthorized("URL has expired".to_string()));
+    }
+    if claims.ip.is_some_and(|ip| ip != client_ip) {
+        return Err(AppError::Unauthorized(
+            "URL is bound to another address".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Wraps the data key of a file so it can travel inside its signed URLs.
+///
+/// The key is bound to the file, and only the server can unwrap it again.
+pub fn wrap_url_key(
+    keys: &SignedUrlKeys,
+    file_id: ObjectId,
+    data_key: &[u8; 32],
+) -> Result<String, AppError> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let wrapped_key = keys
+        .url_key_cipher()
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: data_key,
+                aad: &file_id.bytes(),
+            },
+        )
+        .map_err(|e| AppError::Internal(format!("Error in wrapping file key: {}", e)))?;
+
+    Ok(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &wrapped_key].concat()))
+}
+
+/// Unwraps a data key wrapped by `wrap_url_key`.
+pub fn unwrap_url_key(
+    keys: &SignedUrlKeys,
+    file_id: ObjectId,
+    wrapped_key: &str,
+) -> Result<[u8; 32], AppError> {
+    let invalid = || AppError::Unauthorized("Invalid URL key".to_string());
+
+    let wrapped_key = URL_SAFE_NO_PAD.decode(wrapped_key).map_err(|_| invalid())?;
+    if wrapped_key.len() <= NONCE_SIZE {
+        return Err(invalid());
+    }
+    let (nonce, wrapped_key) = wrapped_key.split_at(NONCE_SIZE);
+
+    keys.url_key_cipher()
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: wrapped_key,
+                aad: &file_id.bytes(),
+            },
+        )
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn claims(now: DateTime<Utc>) -> SignedUrlClaims {
+        SignedUrlClaims {
+            file_id: ObjectId::parse_str("6811a257200ffe8eb047b776").unwrap(),
+            expires: (now + Duration::minutes(10)).timestamp(),
+            ip: Some("203.0.113.7".parse().unwrap()),
+            version: 2,
+            key: Some("wrapped".to_string()),
+        }
+    }
+
+    fn verify(keys: &SignedUrlKeys, claims: &SignedUrlClaims, signature: &str) -> bool {
+        let ip = "203.0.113.7".parse().unwrap();
+        verify_download_url(keys, claims, signature, ip, Utc::now()).is_ok()
+    }
+
+    #[test]
+    fn accepts_a_valid_signature() {
+        let keys = SignedUrlKeys::new("secret");
+        let claims = claims(Utc::now());
+
+        assert!(verify(&keys, &claims, &sign_download_url(&keys, &claims)));
+    }
+
+    #[test]
+    fn rejects_tampered_claims() {
+        let keys = SignedUrlKeys::new("secret");
+        let signature = sign_download_url(&keys, &claims(Utc::now()));
+
+        let mut other_file = claims(Utc::now());
+        other_file.file_id = ObjectId::parse_str("6811a257200ffe8eb047b777").unwrap();
+        let mut later = claims(Utc::now());
+        later.expires += 60;
+        let mut unbound = claims(Utc::now());
+        unbound.ip = None;
+        let mut other_version = claims(Utc::now());
+        other_version.version = 1;
+
+        for claims in [other_file, later, unbound, other_version] {
+            assert!(!verify(&keys, &claims, &signature));
+        }
+        assert!(!verify(&keys, &claims(Utc::now()), "not-a-signature"));
+    }
+
+    #[test]
+    fn rejects_an_expired_url() {
+        let keys = SignedUrlKeys::new("secret");
+        let claims = claims(Utc::now() - Duration::minutes(11));
+
+        assert!(!verify(&keys, &claims, &sign_download_url(&keys, &claims)));
+    }
+
+    #[test]
+    fn rejects_another_address() {
+        let keys = SignedUrlKeys::new("secret");
+        let claims = claims(Utc::now());
+        let signature = sign_download_url(&keys, &claims);
+
+        let ip = "198.51.100.1".parse().unwrap();
+        assert!(verify_download_url(&keys, &claims, &signature, ip, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn rejects_a_signature_made_with_another_key() {
+        let claims = claims(Utc::now());
+        let signature = sign_download_url(&SignedUrlKeys::new("other secret"), &claims);
+
+        assert!(!verify(&SignedUrlKeys::new("secret"), &claims, &signature));
+    }
+
+    #[test]
+    fn unwraps_a_url_key_only_for_its_file() {
+        let keys = SignedUrlKeys::new("secret");
+        let file_id = ObjectId::new();
+        let wrapped_key = wrap_url_key(&keys, file_id, &[4; 32]).unwrap();
+
+        assert_eq!(
+            unwrap_url_key(&keys, file_id, &wrapped_key).unwrap(),
+            [4; 32]
+        );
+        assert!(unwrap_url_key(&keys, ObjectId::new(), &wrapped_key).is_err());
+        assert!(unwrap_url_key(&SignedUrlKeys::new("other"), file_id, &wrapped_key).is_err());
+    }
+}
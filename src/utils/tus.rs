@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderValue, Method, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+
+use crate::error::AppError;
+
+/// The only tus protocol version served.
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// Middleware for the tus routes.
+///
+/// Rejects requests made with another protocol version (`412 Precondition Failed`)
+/// and adds the `Tus-Resumable` header to every response, errors included.
+/// `OPTIONS` requests are exempt from the version check, as the spec requires.
+pub async fn tus_resumable(request: Request, next: Next) -> Response<Body> {
+    let version = request
+        .headers()
+        .get("tus-resumable")
+        .and_then(|v| v.to_str().ok());
+
+    let mut response = if request.method() != Method::OPTIONS && version != Some(TUS_VERSION) {
+        let mut response = StatusCode::PRECONDITION_FAILED.into_response();
+        response
+            .headers_mut()
+            .insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+        response
+    } else {
+        next.run(request).await
+    };
+
+    response
+        .headers_mut()
+        .insert("tus-resumable", HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+/// Parses an `Upload-Metadata` header (`key base64value,key2 base64value2`).
+///
+/// # Arguments
+/// * `header` - The raw header value.
+///
+/// # Returns
+/// * `Ok(HashMap)` from keys to the decoded values; keys without a value map to `""`.
+/// * `Err(AppError::BadRequest)` if a value is not valid base64 or UTF-8.
+pub fn parse_metadata(header: &str) -> Result<HashMap<String, String>, AppError> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+
+            let value = STANDARD
+                .decode(value.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| {
+                    AppError::BadRequest(format!("Invalid `Upload-Metadata` value for `{}`", key))
+                })?;
+
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Formats a datetime as an HTTP date, e.g. `Wed, 25 Jun 2025 13:41:00 GMT`.
+pub fn http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
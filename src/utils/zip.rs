@@ -0,0 +1,321 @@
+use axum::body::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use crc32fast::Hasher;
+use futures::{stream, StreamExt};
+
+use crate::{error::AppError, storage::BlobStream};
+
+/// Size of a local file header, without the file name.
+const LOCAL_HEADER_SIZE: u64 = 30;
+/// Size of a data descriptor (with signature).
+const DATA_DESCRIPTOR_SIZE: u64 = 16;
+/// Size of a central directory header, without the file name.
+const CENTRAL_HEADER_SIZE: u64 = 46;
+/// Size of the end of central directory record.
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+
+/// Version 2.0: stored entries with data descriptors.
+const VERSION: u16 = 20;
+/// Sizes and CRC follow the data (bit 3); names are UTF-8 (bit 11).
+const FLAGS: u16 = 0x0808;
+
+/// One file of a ZIP archive, stored without compression.
+pub struct ZipEntry {
+    pub path: String,
+    // Must match what `content` yields
+    pub size: u64,
+    pub content: BlobStream<'static>,
+}
+
+/// Size of the ZIP archive `zip_stream` builds of files with these paths and sizes.
+pub fn zip_size<'a>(entries: impl IntoIterator<Item = (&'a str, u64)>) -> u64 {
+    entries
+        .into_iter()
+        .map(|(path, size)| {
+            LOCAL_HEADER_SIZE
+                + DATA_DESCRIPTOR_SIZE
+                + CENTRAL_HEADER_SIZE
+                + 2 * path.len() as u64
+                + size
+        })
+        .sum::<u64>()
+        + END_OF_CENTRAL_DIRECTORY_SIZE
+}
+
+/// Builds a ZIP archive of the entries while their contents stream through.
+///
+/// Entries are stored, not compressed, so the size of the archive is known up front
+/// (see `zip_size`); the CRC-32 of each entry is only known once it was streamed and
+/// is sent in a data descriptor after it. Archives are limited to 4 GiB (no ZIP64).
+///
+/// # Arguments
+/// * `entries` - The files of the archive, in order.
+/// * `modified_at` - The modification time recorded for every file.
+///
+/// # Returns
+/// A `BlobStream` of the archive; it fails if an entry yields more or fewer bytes
+/// than its `size`.
+pub fn zip_stream(entries: Vec<ZipEntry>, modified_at: DateTime<Utc>) -> BlobStream<'static> {
+    let state = ZipState {
+        entries: entries.into_iter(),
+        current: None,
+        offset: 0,
+        central_directory: Vec::new(),
+        entry_count: 0,
+        is_finished: false,
+        dos_time: dos_time(&modified_at),
+        dos_date: dos_date(&modified_at),
+    };
+
+    stream::try_unfold(state, |mut state| async move {
+        Ok(state.next_part().await?.map(|part| (part, state)))
+    })
+    .boxed()
+}
+
+/// The entry being streamed.
+struct CurrentEntry {
+    path: String,
+    size: u64,
+    content: BlobStream<'static>,
+    crc: Hasher,
+    written: u64,
+    header_offset: u64,
+}
+
+struct ZipState {
+    entries: std::vec::IntoIter<ZipEntry>,
+    current: Option<CurrentEntry>,
+    // Bytes of the archive yielded so far
+    offset: u64,
+    central_directory: Vec<u8>,
+    entry_count: u16,
+    is_finished: bool,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl ZipState {
+    /// Yields the next part of the archive: a header, a piece of an entry, a data
+    /// descriptor or the central directory.
+    async fn next_part(&mut self) -> Result<Option<Bytes>, AppError> {
+        let part = match self.current.as_mut() {
+            Some(current) => match current.content.next().await {
+                Some(chunk) => {
+                    let chunk = chunk?;
+                    current.crc.update(&chunk);
+                    current.written += chunk.len() as u64;
+                    chunk.to_vec()
+                }
+                None => self.finish_entry()?,
+            },
+            None => match self.entries.next() {
+                Some(entry) => self.start_entry(entry)?,
+                None if !self.is_finished => self.finish_archive()?,
+                None => return Ok(None),
+            },
+        };
+
+        self.offset += part.len() as u64;
+        Ok(Some(Bytes::from(part)))
+    }
+
+    /// Builds the local header of an entry and makes it the current one.
+    fn start_entry(&mut self, entry: ZipEntry) -> Result<Vec<u8>, AppError> {
+        let size = to_u32(entry.size)?;
+
+        let mut header = Vec::with_capacity(LOCAL_HEADER_SIZE as usize + entry.path.len());
+        header.extend(0x04034b50u32.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        header.extend(FLAGS.to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // stored
+        header.extend(self.dos_time.to_le_bytes());
+        header.extend(self.dos_date.to_le_bytes());
+        header.extend(0u32.to_le_bytes()); // CRC-32, see the data descriptor
+        header.extend(size.to_le_bytes());
+        header.extend(size.to_le_bytes());
+        header.extend(name_length(&entry.path)?.to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field length
+        header.extend(entry.path.as_bytes());
+
+        self.current = Some(CurrentEntry {
+            path: entry.path,
+            size: entry.size,
+            content: entry.content,
+            crc: Hasher::new(),
+            written: 0,
+            header_offset: self.offset,
+        });
+
+        Ok(header)
+    }
+
+    /// Builds the data descriptor of the current entry and records it in the central directory.
+    fn finish_entry(&mut self) -> Result<Vec<u8>, AppError> {
+        let current = self
+            .current
+            .take()
+            .ok_or_else(|| AppError::Internal("No ZIP entry is being written".to_string()))?;
+
+        if current.written != current.size {
+            return Err(AppError::Internal(format!(
+                "`{}` has {} bytes instead of {}",
+                current.path, current.written, current.size
+            )));
+        }
+
+        let crc = current.crc.finalize();
+        let size = to_u32(current.size)?;
+
+        let mut descriptor = Vec::with_capacity(DATA_DESCRIPTOR_SIZE as usize);
+        descriptor.extend(0x08074b50u32.to_le_bytes());
+        descriptor.extend(crc.to_le_bytes());
+        descriptor.extend(size.to_le_bytes());
+        descriptor.extend(size.to_le_bytes());
+
+        let directory = &mut self.central_directory;
+        directory.extend(0x02014b50u32.to_le_bytes());
+        directory.extend(VERSION.to_le_bytes()); // made by
+        directory.extend(VERSION.to_le_bytes()); // needed to extract
+        directory.extend(FLAGS.to_le_bytes());
+        directory.extend(0u16.to_le_bytes()); // stored
+        directory.extend(self.dos_time.to_le_bytes());
+        directory.extend(self.dos_date.to_le_bytes());
+        directory.extend(crc.to_le_bytes());
+        directory.extend(size.to_le_bytes());
+        directory.extend(size.to_le_bytes());
+        directory.extend(name_length(&current.path)?.to_le_bytes());
+        directory.extend(0u16.to_le_bytes()); // extra field length
+        directory.extend(0u16.to_le_bytes()); // comment length
+        directory.extend(0u16.to_le_bytes()); // disk number
+        directory.extend(0u16.to_le_bytes()); // internal attributes
+        directory.extend(0u32.to_le_bytes()); // external attributes
+        directory.extend(to_u32(current.header_offset)?.to_le_bytes());
+        directory.extend(current.path.as_bytes());
+
+        self.entry_count = self
+            .entry_count
+            .checked_add(1)
+            .ok_or_else(|| AppError::Internal("Too many files for a ZIP archive".to_string()))?;
+
+        Ok(descriptor)
+    }
+
+    /// Builds the central directory and the end of central directory record.
+    fn finish_archive(&mut self) -> Result<Vec<u8>, AppError> {
+        let mut directory = std::mem::take(&mut self.central_directory);
+        let directory_size = to_u32(directory.len() as u64)?;
+
+        directory.extend(0x06054b50u32.to_le_bytes());
+        directory.extend(0u16.to_le_bytes()); // disk number
+        directory.extend(0u16.to_le_bytes()); // disk with the central directory
+        directory.extend(self.entry_count.to_le_bytes());
+        directory.extend(self.entry_count.to_le_bytes());
+        directory.extend(directory_size.to_le_bytes());
+        directory.extend(to_u32(self.offset)?.to_le_bytes());
+        directory.extend(0u16.to_le_bytes()); // comment length
+
+        self.is_finished = true;
+        Ok(directory)
+    }
+}
+
+fn to_u32(value: u64) -> Result<u32, AppError> {
+    u32::try_from(value).map_err(|_| AppError::Internal("ZIP archive is too large".to_string()))
+}
+
+fn name_length(path: &str) -> Result<u16, AppError> {
+    u16::try_from(path.len()).map_err(|_| AppError::Internal("Path is too long".to_string()))
+}
+
+/// MS-DOS time: hours, minutes and seconds / 2.
+fn dos_time(date: &DateTime<Utc>) -> u16 {
+    ((date.hour() << 11) | (date.minute() << 5) | (date.second() / 2)) as u16
+}
+
+/// MS-DOS date: years since 1980, month and day.
+fn dos_date(date: &DateTime<Utc>) -> u16 {
+    let year = (date.year() - 1980).clamp(0, 127) as u32;
+    ((year << 9) | (date.month() << 5) | date.day()) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    fn entry(path: &str, chunks: &[&'static [u8]]) -> ZipEntry {
+        ZipEntry {
+            path: path.to_string(),
+            size: chunks.iter().map(|chunk| chunk.len() as u64).sum(),
+            content: stream::iter(
+                chunks
+                    .iter()
+                    .map(|chunk| Ok(Bytes::from_static(chunk)))
+                    .collect::<Vec<_>>(),
+            )
+            .boxed(),
+        }
+    }
+
+    async fn zip(entries: Vec<ZipEntry>) -> Result<Vec<u8>, AppError> {
+        let parts = zip_stream(entries, Utc::now())
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(parts.concat())
+    }
+
+    #[tokio::test]
+    async fn streams_an_archive_of_the_announced_size() {
+        let files: [(&str, &[&[u8]]); 3] = [
+            ("notes.txt", &[b"Hello ", b"from ", b"a bundle!"]),
+            (
+                "photos/caf\u{e9}.jpg",
+                &[&[0xff, 0xd8, 0xff, 0xe0], &[0; 1000]],
+            ),
+            ("empty", &[]),
+        ];
+        let entries = files
+            .iter()
+            .map(|(path, chunks)| entry(path, chunks))
+            .collect::<Vec<_>>();
+        let size = zip_size(
+            entries
+                .iter()
+                .map(|entry| (entry.path.as_str(), entry.size)),
+        );
+
+        let archive = zip(entries).await.unwrap();
+        assert_eq!(archive.len() as u64, size);
+
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(archive.len(), files.len());
+
+        for (index, (path, chunks)) in files.iter().enumerate() {
+            let content = chunks.concat();
+            let mut file = archive.by_index(index).unwrap();
+
+            assert_eq!(file.name(), *path);
+            assert_eq!(file.size(), content.len() as u64);
+            assert_eq!(file.crc32(), crc32fast::hash(&content));
+
+            let mut read = Vec::new();
+            file.read_to_end(&mut read).unwrap();
+            assert_eq!(read, content);
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_if_an_entry_does_not_match_its_size() {
+        let mut short = entry("short", &[b"abc"]);
+        short.size = 4;
+        assert!(zip(vec![short]).await.is_err());
+
+        let mut long = entry("long", &[b"abc"]);
+        long.size = 2;
+        assert!(zip(vec![long]).await.is_err());
+    }
+}